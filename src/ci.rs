@@ -0,0 +1,274 @@
+use crate::error::Result;
+use crate::service::{detect, detect_with_remote_and_branch, GitService};
+use std::env;
+use std::path::Path;
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn github_actions() -> Option<(String, Option<String>)> {
+    let repo = var("GITHUB_REPOSITORY")?;
+    let server = var("GITHUB_SERVER_URL").unwrap_or_else(|| "https://github.com".to_string());
+    let url = format!("{}/{}", server.trim_end_matches('/'), repo);
+    // Note: GITHUB_HEAD_REF is only set on pull_request events and contains the source branch.
+    // GITHUB_REF_NAME is a tag name on tag pushes so it is used only when the ref is a branch.
+    let branch = var("GITHUB_HEAD_REF")
+        .or_else(|| {
+            var("GITHUB_REF_NAME").filter(|_| var("GITHUB_REF_TYPE").as_deref() == Some("branch"))
+        })
+        .or_else(|| {
+            var("GITHUB_REF").and_then(|r| r.strip_prefix("refs/heads/").map(str::to_string))
+        });
+    Some((url, branch))
+}
+
+fn gitlab_ci() -> Option<(String, Option<String>)> {
+    let url = var("CI_PROJECT_URL")?;
+    Some((url, var("CI_COMMIT_BRANCH")))
+}
+
+fn bitbucket_pipelines() -> Option<(String, Option<String>)> {
+    let repo = var("BITBUCKET_REPO_FULL_NAME")?;
+    let url = format!("https://bitbucket.org/{}", repo);
+    Some((url, var("BITBUCKET_BRANCH")))
+}
+
+// Environment variables containing a remote URL checked out by CI like Jenkins
+const URL_VARS: &[&str] = &["GIT_URL", "CI_REPOSITORY_URL", "BUILD_REPOSITORY_URI"];
+
+/// Detect Git hosting service from environment variables set by well-known CI
+/// services (GitHub Actions, GitLab CI and Bitbucket Pipelines). Next, remote
+/// URLs in `GIT_URL` (Jenkins), `CI_REPOSITORY_URL` (GitLab CI) and
+/// `BUILD_REPOSITORY_URI` (Azure Pipelines) are tried. When none of them is
+/// set, it falls back to detecting the service from the current directory
+/// with `git` command.
+pub fn detect_from_env() -> Result<GitService> {
+    detect_from_env_with_url_vars(URL_VARS)
+}
+
+/// Almost the same as `detect_from_env`, but environment variables
+/// containing a remote URL are specified by `url_vars`. The first variable
+/// whose value is classified as a service is used.
+pub fn detect_from_env_with_url_vars(url_vars: &[&str]) -> Result<GitService> {
+    let found = github_actions()
+        .or_else(gitlab_ci)
+        .or_else(bitbucket_pipelines);
+    if let Some((url, branch)) = found {
+        return detect_with_remote_and_branch(url, branch);
+    }
+    for name in url_vars {
+        if let Some(url) = var(name) {
+            if let Ok(service) = detect_with_remote_and_branch(url, None) {
+                return Ok(service);
+            }
+        }
+    }
+    detect(Path::new("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ServiceKind;
+    use std::sync::Mutex;
+
+    // Environment variables are process-global. Tests touching them must not run in parallel.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    const VARS: &[&str] = &[
+        "GITHUB_REPOSITORY",
+        "GITHUB_SERVER_URL",
+        "GITHUB_HEAD_REF",
+        "GITHUB_REF_NAME",
+        "GITHUB_REF_TYPE",
+        "GITHUB_REF",
+        "CI_PROJECT_URL",
+        "CI_COMMIT_BRANCH",
+        "BITBUCKET_REPO_FULL_NAME",
+        "BITBUCKET_BRANCH",
+        "GIT_URL",
+        "CI_REPOSITORY_URL",
+        "BUILD_REPOSITORY_URI",
+        "MY_REPO_URL",
+    ];
+
+    fn with_env<F: FnOnce()>(vars: &[(&str, &str)], f: F) {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let saved: Vec<_> = VARS.iter().map(|n| (*n, env::var(n).ok())).collect();
+        for name in VARS {
+            env::remove_var(name);
+        }
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        f();
+        for (name, value) in saved {
+            match value {
+                Some(v) => env::set_var(name, v),
+                None => env::remove_var(name),
+            }
+        }
+    }
+
+    #[test]
+    fn github_repository() {
+        with_env(
+            &[
+                ("GITHUB_REPOSITORY", "rhysd/detect_git_service"),
+                ("GITHUB_REF_NAME", "main"),
+                ("GITHUB_REF_TYPE", "branch"),
+            ],
+            || {
+                let service = detect_from_env().unwrap();
+                match service {
+                    GitService::GitHub {
+                        ref user,
+                        ref repo,
+                        ref branch,
+                        ..
+                    } => {
+                        assert_eq!(user, "rhysd");
+                        assert_eq!(repo, "detect_git_service");
+                        assert_eq!(branch.as_ref().unwrap(), "main");
+                    }
+                    _ => panic!("unexpected service: {:?}", service),
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn github_tag_push() {
+        with_env(
+            &[
+                ("GITHUB_REPOSITORY", "rhysd/detect_git_service"),
+                ("GITHUB_REF", "refs/tags/v1.0"),
+                ("GITHUB_REF_NAME", "v1.0"),
+                ("GITHUB_REF_TYPE", "tag"),
+            ],
+            || {
+                let service = detect_from_env().unwrap();
+                assert_eq!(service.kind(), ServiceKind::GitHub);
+                assert_eq!(service.branch(), &None);
+            },
+        );
+    }
+
+    #[test]
+    fn github_enterprise_server_url() {
+        with_env(
+            &[
+                ("GITHUB_REPOSITORY", "rhysd/detect_git_service"),
+                ("GITHUB_SERVER_URL", "https://github.mycompany.com"),
+                ("GITHUB_REF", "refs/heads/feature"),
+            ],
+            || {
+                let service = detect_from_env().unwrap();
+                match service {
+                    GitService::GitHubEnterprise { ref branch, .. } => {
+                        assert_eq!(branch.as_ref().unwrap(), "feature");
+                    }
+                    _ => panic!("unexpected service: {:?}", service),
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn gitlab_project_url() {
+        with_env(
+            &[(
+                "CI_PROJECT_URL",
+                "https://gitlab.com/Linda_pp/detect_git_service",
+            )],
+            || {
+                let service = detect_from_env().unwrap();
+                match service {
+                    GitService::GitLab {
+                        ref user, ref repo, ..
+                    } => {
+                        assert_eq!(user, "Linda_pp");
+                        assert_eq!(repo, "detect_git_service");
+                    }
+                    _ => panic!("unexpected service: {:?}", service),
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn gitlab_project_url_with_subgroup() {
+        with_env(
+            &[
+                (
+                    "CI_PROJECT_URL",
+                    "https://gitlab.com/group/subgroup/project",
+                ),
+                ("CI_COMMIT_BRANCH", "main"),
+            ],
+            || {
+                let service = detect_from_env().unwrap();
+                assert_eq!(service.kind(), ServiceKind::GitLab);
+                assert_eq!(service.user(), "group/subgroup");
+                assert_eq!(service.repo(), "project");
+                assert_eq!(service.path_segments(), &["group", "subgroup", "project"]);
+                assert_eq!(service.branch().as_deref(), Some("main"));
+            },
+        );
+    }
+
+    #[test]
+    fn git_url() {
+        with_env(
+            &[
+                ("GIT_URL", "not a url"),
+                (
+                    "CI_REPOSITORY_URL",
+                    "ssh://git@gitlab.com/Linda_pp/detect_git_service.git",
+                ),
+            ],
+            || {
+                let service = detect_from_env().unwrap();
+                assert_eq!(service.kind(), ServiceKind::GitLab);
+                assert_eq!(service.user(), "Linda_pp");
+                assert_eq!(service.repo(), "detect_git_service");
+                assert_eq!(service.branch(), &None);
+            },
+        );
+        with_env(
+            &[("GIT_URL", "git@bitbucket.org:rhysd/detect_git_service.git")],
+            || {
+                let service = detect_from_env().unwrap();
+                assert_eq!(service.kind(), ServiceKind::Bitbucket);
+                assert_eq!(service.user(), "rhysd");
+            },
+        );
+    }
+
+    #[test]
+    fn custom_url_vars() {
+        with_env(
+            &[
+                ("GIT_URL", "git@bitbucket.org:rhysd/detect_git_service.git"),
+                (
+                    "MY_REPO_URL",
+                    "git@gitlab.com:Linda_pp/detect_git_service.git",
+                ),
+            ],
+            || {
+                let service = detect_from_env_with_url_vars(&["MY_REPO_URL"]).unwrap();
+                assert_eq!(service.kind(), ServiceKind::GitLab);
+            },
+        );
+    }
+
+    #[test]
+    fn fallback_to_git() {
+        with_env(&[], || {
+            let service = detect_from_env().unwrap();
+            assert_eq!(service.user(), "rhysd");
+            assert_eq!(service.repo(), "detect_git_service");
+        });
+    }
+} // mod tests
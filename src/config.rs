@@ -0,0 +1,588 @@
+use crate::error::{Error, Result};
+use crate::remote::{apply_instead_of, trim_url};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn read_file(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|error| Error::CannotReadFile {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut spaces = String::new();
+    let mut in_quote = false;
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quote = !in_quote,
+            '\\' => {
+                value.push_str(&spaces);
+                spaces.clear();
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => value.push('\u{8}'),
+                    Some(c) => value.push(c),
+                    None => {}
+                }
+                continue;
+            }
+            ';' | '#' if !in_quote => break,
+            c if c.is_whitespace() && !in_quote => {
+                spaces.push(c);
+                continue;
+            }
+            c => {
+                value.push_str(&spaces);
+                spaces.clear();
+                value.push(c);
+                continue;
+            }
+        }
+        // Note: Spaces before a quote are not trailing
+        value.push_str(&spaces);
+        spaces.clear();
+    }
+    value
+}
+
+fn parse_section(line: &str) -> Option<String> {
+    let line = line.strip_prefix('[')?;
+    let end = line.find(|c: char| c == ']' || c == '"' || c.is_whitespace())?;
+    let name = line[..end].to_ascii_lowercase();
+    let rest = line[end..].trim_start();
+    if let Some(quoted) = rest.strip_prefix('"') {
+        // [section "subsection"] form. Subsection is case sensitive
+        let mut sub = String::new();
+        let mut chars = quoted.chars();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => sub.push(chars.next()?),
+                c => sub.push(c),
+            }
+        }
+        Some(format!("{}.{}", name, sub))
+    } else if rest.starts_with(']') {
+        // [section] or deprecated [section.subsection] form
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Entries of Git config file. Keys are normalized to `section.subsection.name` where section
+/// and name are lowercased.
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    pub fn parse(src: &str) -> Config {
+        let mut entries = vec![];
+        let mut section = None;
+        let mut lines = src.lines();
+        while let Some(line) = lines.next() {
+            let mut line = line.trim().to_string();
+            // Join lines continued with backslash at end of line
+            while line.ends_with('\\') && (line.len() - line.trim_end_matches('\\').len()) % 2 == 1
+            {
+                line.pop();
+                match lines.next() {
+                    Some(next) => line.push_str(next),
+                    None => break,
+                }
+            }
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = parse_section(&line);
+                continue;
+            }
+            let section = match &section {
+                Some(s) => s,
+                None => continue,
+            };
+            let (name, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), parse_value(&line[i + 1..])),
+                None => (line.as_str(), "true".to_string()), // Boolean key without value
+            };
+            let key = format!("{}.{}", section, name.to_ascii_lowercase());
+            entries.push((key, value));
+        }
+        Config { entries }
+    }
+
+    pub fn read(path: &Path) -> Result<Config> {
+        read_file(path).map(|src| Config::parse(&src))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        // Note: When the same key is specified multiple times, the last one wins
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// Resolve `.git` file used by linked worktrees and submodules. It contains a path to the actual
+// Git directory like 'gitdir: /path/to/repo/.git/worktrees/foo'.
+fn resolve_git_file(dot_git: &Path) -> Result<PathBuf> {
+    let content = read_file(dot_git)?;
+    match content.trim().strip_prefix("gitdir:") {
+        Some(dir) => Ok(dot_git.parent().unwrap().join(dir.trim())),
+        None => Err(Error::CannotDetect {
+            reason: format!("Invalid .git file {}", dot_git.display()),
+        }),
+    }
+}
+
+// Working directories of other VCS which are often mistaken for Git repositories
+const OTHER_VCS: &[(&str, &str)] = &[(".hg", "Mercurial"), (".pijul", "Pijul")];
+
+// Error for a path outside Git repositories. When the path is in a working directory of other VCS,
+// the VCS is named in the error since the mistake is likely.
+pub fn not_a_git_repository(path: &Path, dir: &Path) -> Error {
+    for ancestor in dir.ancestors() {
+        if let Some((_, vcs)) = OTHER_VCS.iter().find(|(d, _)| ancestor.join(d).is_dir()) {
+            return Error::CannotDetect {
+                reason: format!(
+                    "{} is in {} repository at {}, not in Git repository",
+                    path.display(),
+                    vcs,
+                    ancestor.display(),
+                ),
+            };
+        }
+    }
+    Error::NotAGitRepository {
+        path: path.to_path_buf(),
+    }
+}
+
+// Resolve symbolic links in the path. The path is returned as-is when it contains no link so that
+// paths shown to users are kept as they are.
+pub fn resolve_symlinks(path: &Path) -> PathBuf {
+    let is_link = |p: &Path| {
+        fs::symlink_metadata(p)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false)
+    };
+    if !path.ancestors().any(is_link) {
+        return path.to_path_buf();
+    }
+    match path.canonicalize() {
+        Ok(canonical) => strip_verbatim_prefix(canonical),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// `canonicalize` returns a verbatim path like '\\?\C:\foo' on Windows. It is converted to a normal
+// path like 'C:\foo' since git command and users don't expect it.
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    if cfg!(windows) {
+        if let Some(s) = path.to_str() {
+            if let Some(unc) = s.strip_prefix(r"\\?\UNC\") {
+                return PathBuf::from(format!(r"\\{}", unc));
+            }
+            if let Some(rest) = s.strip_prefix(r"\\?\") {
+                return PathBuf::from(rest);
+            }
+        }
+    }
+    path
+}
+
+// Global config files read by Git. System-wide config is ignored as well as git command run by this
+// library. Files which don't exist are skipped.
+fn global_configs() -> Vec<Config> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let xdg = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+    let files = vec![
+        xdg.map(|d| d.join("git").join("config")),
+        home.map(|h| h.join(".gitconfig")),
+    ];
+    files
+        .into_iter()
+        .flatten()
+        .filter_map(|f| Config::read(&f).ok())
+        .collect()
+}
+
+// Pairs of base and prefix of `url.<base>.insteadOf` in the config file
+fn instead_of_rules(config: &Config) -> impl Iterator<Item = (String, String)> + '_ {
+    config.entries().filter_map(|(key, prefix)| {
+        let base = key.strip_prefix("url.")?.strip_suffix(".insteadof")?;
+        Some((base.to_string(), prefix.to_string()))
+    })
+}
+
+fn remote_url_of(config: &Config, name: &str) -> Result<String> {
+    match config.get(&format!("remote.{}.url", name)) {
+        Some(url) => {
+            // Note: Rules are usually put in global config
+            let globals = global_configs();
+            let rules: Vec<_> = globals
+                .iter()
+                .chain(Some(config))
+                .flat_map(instead_of_rules)
+                .collect();
+            Ok(apply_instead_of(trim_url(url), &rules))
+        }
+        None => Err(Error::CannotDetect {
+            reason: format!("URL of remote '{}' is not configured", name),
+        }),
+    }
+}
+
+/// Local Git repository found on file system.
+#[derive(Debug)]
+pub struct Repository {
+    /// Git directory. For linked worktrees, this is a worktree-specific directory.
+    git_dir: PathBuf,
+}
+
+impl Repository {
+    pub fn find(path: &Path) -> Result<Repository> {
+        let mut dir = if path.is_file() {
+            path.parent().unwrap().to_path_buf()
+        } else {
+            path.to_path_buf()
+        };
+        if dir.is_relative() {
+            let cwd = env::current_dir().map_err(|error| Error::CannotReadFile {
+                path: dir.clone(),
+                error,
+            })?;
+            dir = cwd.join(dir);
+        }
+        // Resolve symbolic links since ancestors of a link are not ancestors of the linked directory
+        let dir = resolve_symlinks(&dir);
+
+        // Note: `Path::ancestors` only removes the last component lexically and never follows
+        // symbolic links, so the walk always terminates at the root after as many steps as the
+        // number of components. Symlink loops cannot make it infinite since links were resolved
+        // above (or canonicalization failed and the path is used as-is).
+        for ancestor in dir.ancestors() {
+            let dot_git = ancestor.join(".git");
+            if dot_git.is_dir() {
+                return Ok(Repository { git_dir: dot_git });
+            }
+            if dot_git.is_file() {
+                let git_dir = resolve_git_file(&dot_git)?;
+                return Ok(Repository { git_dir });
+            }
+        }
+
+        Err(not_a_git_repository(path, &dir))
+    }
+
+    // Directory shared among all worktrees. It contains config file.
+    fn common_dir(&self) -> PathBuf {
+        match fs::read_to_string(self.git_dir.join("commondir")) {
+            Ok(common) => self.git_dir.join(common.trim()),
+            Err(_) => self.git_dir.clone(),
+        }
+    }
+
+    pub fn config(&self) -> Result<Config> {
+        Config::read(&self.common_dir().join("config"))
+    }
+
+    // Each worktree has its own HEAD
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        let head = read_file(&self.git_dir.join("HEAD"))?;
+        Ok(head
+            .trim()
+            .strip_prefix("ref: refs/heads/")
+            .map(str::to_string))
+    }
+
+    // Remote name and branch name of the upstream of the current branch. It returns `None` when no
+    // upstream is configured.
+    pub fn upstream(&self) -> Result<Option<(String, String)>> {
+        let config = self.config()?;
+        let current = match self.current_branch()? {
+            Some(b) => b,
+            None => return Ok(None),
+        };
+        let remote = config.get(&format!("branch.{}.remote", current));
+        let merge = config
+            .get(&format!("branch.{}.merge", current))
+            .and_then(|m| m.strip_prefix("refs/heads/"));
+        match (remote, merge) {
+            (Some(r), Some(m)) => Ok(Some((r.to_string(), m.to_string()))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn tracking_remote(&self) -> Result<(String, Option<String>)> {
+        match self.upstream()? {
+            Some((remote, branch)) => self.remote_url(&remote).map(|url| (url, Some(branch))),
+            None => {
+                let url = self.remote_url("origin")?;
+                Ok((url, self.current_branch()?))
+            }
+        }
+    }
+
+    pub fn remote_url(&self, name: &str) -> Result<String> {
+        remote_url_of(&self.config()?, name)
+    }
+
+    // Pairs of remote name and URL of all remotes in the config file
+    pub fn all_remote_urls(&self) -> Result<Vec<(String, String)>> {
+        let config = self.config()?;
+        let mut names: Vec<&str> = vec![];
+        for (key, _) in config.entries() {
+            if let Some(name) = key
+                .strip_prefix("remote.")
+                .and_then(|k| k.strip_suffix(".url"))
+            {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+            .into_iter()
+            .map(|name| remote_url_of(&config, name).map(|url| (name.to_string(), url)))
+            .collect()
+    }
+
+    pub fn preferred_remote(&self, names: &[String]) -> Result<(String, Option<String>)> {
+        let config = self.config()?;
+        match names.iter().find_map(|n| remote_url_of(&config, n).ok()) {
+            Some(url) => Ok((url, self.current_branch()?)),
+            None => Err(Error::CannotDetect {
+                reason: format!("None of remotes {} exists", names.join(", ")),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            r#"
+# comment
+[core]
+	bare = false
+	ignorecase
+[remote "origin"]
+	url = https://github.com/rhysd/detect_git_service.git  ; trailing comment
+	fetch = +refs/heads/*:refs/remotes/origin/*
+[Remote "Upstream"]
+	URL = "git@github.com:foo/detect_git_service.git"
+[branch "feat"]
+	remote = origin
+	merge = refs/heads/\
+feat
+[alias]
+	hello = "echo \"hello  # world\""
+"#,
+        );
+        assert_eq!(config.get("core.bare"), Some("false"));
+        assert_eq!(config.get("core.ignorecase"), Some("true"));
+        assert_eq!(
+            config.get("remote.origin.url"),
+            Some("https://github.com/rhysd/detect_git_service.git"),
+        );
+        assert_eq!(
+            config.get("remote.Upstream.url"),
+            Some("git@github.com:foo/detect_git_service.git"),
+        );
+        assert_eq!(config.get("branch.feat.merge"), Some("refs/heads/feat"));
+        assert_eq!(config.get("alias.hello"), Some("echo \"hello  # world\""));
+        assert_eq!(config.get("remote.unknown.url"), None);
+    }
+
+    #[test]
+    fn unusual_remote_url_values() {
+        let config = Config::parse(
+            r#"
+[remote "quoted"]
+	url = "https://github.com/rhysd/detect_git_service.git"
+[remote "spaces"]
+	url = "  https://github.com/rhysd/detect_git_service.git  "
+[remote "single"]
+	url = 'https://github.com/rhysd/detect_git_service.git'
+"#,
+        );
+        for name in &["quoted", "spaces", "single"] {
+            assert_eq!(
+                remote_url_of(&config, name).unwrap(),
+                "https://github.com/rhysd/detect_git_service.git",
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn remote_url_with_instead_of() {
+        let dir = temp_dir("config-remote-url-instead-of");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".git").join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            dir.join(".git").join("config"),
+            "[remote \"origin\"]\n\turl = gh:rhysd/detect_git_service.git\n\
+             [url \"https://github.com/\"]\n\tinsteadOf = gh:\n",
+        )
+        .unwrap();
+        let repo = Repository::find(&dir).unwrap();
+        assert_eq!(
+            repo.remote_url("origin").unwrap(),
+            "https://github.com/rhysd/detect_git_service.git"
+        );
+    }
+
+    #[test]
+    fn tracking_remote_of_this_repo() {
+        let repo = Repository::find(Path::new(".")).unwrap();
+        let (url, _) = repo.tracking_remote().unwrap();
+        assert!(url.contains("detect_git_service"), "{}", url);
+    }
+
+    #[test]
+    fn linked_worktree() {
+        let root = temp_dir("config-linked-worktree");
+        let common = root.join("main").join(".git");
+        let wt_git_dir = common.join("worktrees").join("wt");
+        fs::create_dir_all(&wt_git_dir).unwrap();
+        fs::write(
+            common.join("config"),
+            "[remote \"origin\"]\n\turl = https://github.com/rhysd/detect_git_service.git\n",
+        )
+        .unwrap();
+        fs::write(common.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        fs::write(wt_git_dir.join("HEAD"), "ref: refs/heads/feature\n").unwrap();
+        fs::write(wt_git_dir.join("commondir"), "../..\n").unwrap();
+
+        let wt = root.join("wt");
+        fs::create_dir_all(wt.join("src")).unwrap();
+        fs::write(
+            wt.join(".git"),
+            format!("gitdir: {}\n", wt_git_dir.display()),
+        )
+        .unwrap();
+
+        let repo = Repository::find(&wt.join("src")).unwrap();
+        let (url, branch) = repo.tracking_remote().unwrap();
+        assert_eq!(url, "https://github.com/rhysd/detect_git_service.git");
+        assert_eq!(branch, Some("feature".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directory() {
+        let link = temp_dir("config-symlinked-directory").join("link");
+        std::os::unix::fs::symlink(env::current_dir().unwrap().join("src"), &link).unwrap();
+        let repo = Repository::find(&link).unwrap();
+        let (url, _) = repo.tracking_remote().unwrap();
+        assert!(url.contains("detect_git_service"), "{}", url);
+
+        let src = env::current_dir().unwrap().join("src");
+        assert_eq!(resolve_symlinks(&link), src.canonicalize().unwrap());
+        assert_eq!(
+            resolve_symlinks(&link.join("git.rs")),
+            src.join("git.rs").canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn path_without_symlinks_is_kept() {
+        let dir = temp_dir("config-path-without-symlinks");
+        let expected = dir.join("a").join("..").join("b");
+        // Note: Temporary directory itself may be a symbolic link like '/var' on macOS
+        if dir.canonicalize().unwrap() == dir {
+            assert_eq!(resolve_symlinks(&expected), expected);
+        }
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from("/foo/bar")),
+            PathBuf::from("/foo/bar")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn strip_verbatim_prefix_on_windows() {
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\C:\foo\bar")),
+            PathBuf::from(r"C:\foo\bar")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\UNC\server\share")),
+            PathBuf::from(r"\\server\share")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_outside_repository() {
+        let dir = temp_dir("config-symlink-loop");
+        std::os::unix::fs::symlink(dir.join("b"), dir.join("a")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a"), dir.join("b")).unwrap();
+        let path = dir.join("a").join("foo");
+        let err = Repository::find(&path).unwrap_err();
+        match err {
+            Error::NotAGitRepository { path: ref p } => assert_eq!(p, &path),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn deep_path_outside_repository() {
+        let mut path = temp_dir("config-deep-path");
+        for _ in 0..300 {
+            path.push("a");
+        }
+        let err = Repository::find(&path).unwrap_err();
+        match err {
+            Error::NotAGitRepository { path: ref p } => assert_eq!(p, &path),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn mercurial_repository() {
+        let root = temp_dir("config-mercurial-repository");
+        fs::create_dir_all(root.join(".hg")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        let err = Repository::find(&root.join("src")).unwrap_err();
+        assert!(
+            format!("{}", err).contains("in Mercurial repository"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn no_repository() {
+        let err = Repository::find(Path::new("/")).unwrap_err();
+        match err {
+            Error::NotAGitRepository { ref path } => assert_eq!(path, Path::new("/")),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+} // mod tests
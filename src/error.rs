@@ -1,8 +1,13 @@
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
+use std::str;
+use std::time::Duration;
 
-/// Error caused by APIs in detect_git_service crate.
+/// Error caused by APIs in detect_git_service crate. New variants may be added
+/// in the future. Please add a wildcard arm when matching variants.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Error raised when underlying `git` execution has failed.
     GitCommandFailed {
@@ -11,8 +16,30 @@ pub enum Error {
         /// Args used for the command execution.
         args: Vec<String>,
     },
+    /// Error raised when stdout of `git` execution is not a valid UTF-8 sequence.
+    GitOutputNotUtf8 {
+        /// Args used for the command execution.
+        args: Vec<String>,
+        /// Underlying error on the conversion.
+        error: str::Utf8Error,
+    },
+    /// Error raised when `git` execution did not finish within the timeout set
+    /// by `DetectOptions::timeout`. The process is killed.
+    GitCommandTimedOut {
+        /// Args used for the command execution.
+        args: Vec<String>,
+        /// Timeout which was exceeded.
+        timeout: Duration,
+    },
     /// Error raised when a shell command cannot be run as child process.
     CommandCannotRun(io::Error),
+    /// Error raised when a file in Git repository cannot be read.
+    CannotReadFile {
+        /// Path to the file.
+        path: PathBuf,
+        /// Underlying I/O error.
+        error: io::Error,
+    },
     /// Error raised when trying to parse a broken Git URL.
     BrokenUrl {
         /// A broken URL as string.
@@ -20,6 +47,11 @@ pub enum Error {
         /// What was broken.
         msg: String,
     },
+    /// Error raised when the given path is not in any Git repository.
+    NotAGitRepository {
+        /// Path which is not in a Git repository.
+        path: PathBuf,
+    },
     /// Error raised when this library could not find any Git hosting service
     /// from Git URL of the repository.
     CannotDetect {
@@ -42,12 +74,47 @@ impl fmt::Display for Error {
                 }
                 write!(f, "` exited with non-zero status")
             }
+            Error::GitOutputNotUtf8 { args, error } => {
+                write!(f, "`git")?;
+                for arg in args.iter() {
+                    write!(f, " '{}'", arg)?;
+                }
+                write!(f, "` output is not valid UTF-8: {}", error)
+            }
+            Error::GitCommandTimedOut { args, timeout } => {
+                write!(f, "`git")?;
+                for arg in args.iter() {
+                    write!(f, " '{}'", arg)?;
+                }
+                write!(f, "` timed out after {:?}", timeout)
+            }
             Error::CommandCannotRun(err) => write!(f, "{}: cannot run command", err),
+            Error::CannotReadFile { path, error } => {
+                write!(f, "Cannot read file {}: {}", path.display(), error)
+            }
             Error::BrokenUrl { url, msg } => write!(f, "Git URL {} is broken: {}", url, msg),
+            Error::NotAGitRepository { path } => {
+                write!(f, "Not a Git repository: {}", path.display())
+            }
             Error::CannotDetect { reason } => write!(f, "Cannot detect service: {}", reason),
         }
     }
 }
 
 /// Result type dedicated for detect_git_service crate.
+///
+/// ```
+/// use detect_git_service::{detect_from_url, Error, Result};
+///
+/// fn repo_name(url: &str) -> Result<String> {
+///     detect_from_url(url).map(|s| s.repo().to_string())
+/// }
+///
+/// assert_eq!(repo_name("https://github.com/rhysd/foo.git").unwrap(), "foo");
+/// match repo_name("https://example.com/rhysd/foo.git") {
+///     Err(Error::CannotDetect { .. }) => {}
+///     Err(err) => panic!("unexpected error: {}", err),
+///     Ok(name) => panic!("unexpected success: {}", name),
+/// }
+/// ```
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,109 +1,530 @@
+use crate::config::{not_a_git_repository, resolve_symlinks};
 use crate::error::{Error, Result};
+use crate::remote::{apply_instead_of, trim_url};
+use std::cell::OnceCell;
+use std::env;
 use std::ffi::OsStr;
 use std::fmt::Debug;
-use std::path::Path;
-use std::process::Command;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
 use std::str;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Resolve a directory to run git command. Relative path is resolved to an absolute path so that
+// the directory does not depend on the current directory of the command.
+// When the path does not exist (e.g. a path in a backtrace of other machine), the nearest existing
+// ancestor directory is used. git command walks up from the directory to find the repository.
+// Symbolic links are resolved so that a symlinked checkout is detected as the linked repository.
+fn resolve_dir(path: &Path) -> PathBuf {
+    let path = absolute(path);
+    match path.ancestors().find(|p| p.is_dir()) {
+        Some(dir) => resolve_symlinks(dir),
+        None => path,
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Parse output of `git config --get-regexp` for remote URLs into pairs of remote name and URL. Each
+// line is formatted as 'remote.{name}.url {url}'. Remote name may contain dots.
+fn parse_remote_url_entries(stdout: &str) -> Vec<(String, String)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut entries = line.splitn(2, ' ');
+            let key = entries.next()?;
+            let url = entries.next().unwrap_or("").trim();
+            let name = key.strip_prefix("remote.")?.strip_suffix(".url")?;
+            Some((name.to_string(), trim_url(url).to_string()))
+        })
+        .collect()
+}
+
+// Read all bytes from a pipe of child process in another thread so that the child is never blocked
+// by a full pipe while its exit is polled.
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+// Run the command and collect its output like `Command::output`. When the command does not exit
+// before the timeout, it is killed and `None` is returned.
+fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> io::Result<Option<Output>> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(Output {
+                status,
+                stdout: stdout.join().unwrap_or_default(),
+                stderr: stderr.join().unwrap_or_default(),
+            }));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            // Note: Reader threads are not joined since grandchildren may still hold the pipes
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(10)));
+    }
+}
+
+// Parse output of `git config --get-regexp` for `url.<base>.insteadOf` into pairs of base and
+// prefix. Each line is formatted as 'url.{base}.insteadof {prefix}'. Base may contain spaces.
+fn parse_instead_of_entries(stdout: &str) -> Vec<(String, String)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("url.")?;
+            let idx = rest.find(".insteadof ")?;
+            let prefix = rest[idx + ".insteadof ".len()..].trim();
+            Some((rest[..idx].to_string(), prefix.to_string()))
+        })
+        .collect()
+}
+
+// Version numbers of git command like (2, 39, 2)
+pub type Version = (u32, u32, u32);
+
+// Parse output of `git --version` like 'git version 2.39.2 (Apple Git-143)' into version numbers.
+fn parse_version(stdout: &str) -> Option<Version> {
+    let version = stdout
+        .strip_prefix("git version ")?
+        .split_whitespace()
+        .next()?;
+    let mut nums = version.split('.').map(|s| s.parse::<u32>().ok());
+    let major = nums.next()??;
+    let minor = nums.next()??;
+    let patch = nums.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Local branch in a repository with its upstream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchInfo {
+    /// Name of the branch like `main`
+    pub name: String,
+    /// Upstream of the branch like `origin/main` if configured
+    pub upstream: Option<String>,
+    /// Whether the branch is checked out currently
+    pub is_current: bool,
+}
+
+// Format of `git for-each-ref` to list branches. Fields are separated by NUL
+const BRANCH_FORMAT: &str = "--format=%(refname:short)%00%(upstream:short)%00%(HEAD)";
+
+// Parse output of `git for-each-ref` with `BRANCH_FORMAT`. '%(HEAD)' is '*' for the current branch
+// and ' ' for others. Note that trailing ' ' of the last line may be trimmed.
+fn parse_branches(stdout: &str) -> Vec<BranchInfo> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\0');
+            let name = fields.next().filter(|n| !n.is_empty())?;
+            let upstream = fields.next().filter(|u| !u.is_empty());
+            let is_current = fields.next() == Some("*");
+            Some(BranchInfo {
+                name: name.to_string(),
+                upstream: upstream.map(str::to_string),
+                is_current,
+            })
+        })
+        .collect()
+}
 
 pub struct Git<'a> {
     command: &'a str,
-    path: &'a Path,
+    // Path given by the caller. It is used for error messages
+    path: PathBuf,
+    dir: PathBuf,
+    git_dir: Option<PathBuf>,
+    version: OnceCell<Option<Version>>,
+    shared_version: Option<&'a OnceLock<Option<Version>>>,
+    envs: &'a [(String, String)],
+    timeout: Option<Duration>,
+    on_debug: Option<&'a dyn Fn(&str)>,
 }
 
 impl<'a> Git<'a> {
-    fn dir(&self) -> &Path {
-        if self.path.is_file() {
-            self.path.parent().unwrap()
-        } else {
-            self.path
+    fn debug(&self, msg: &str) {
+        if let Some(f) = self.on_debug {
+            f(msg);
         }
     }
 
+    // Note: This library never runs git commands which access network such as `fetch`. SSH
+    // configurations like `core.sshCommand` never affect detection.
     pub fn command<S: AsRef<OsStr> + Debug>(&self, args: &[S]) -> Result<String> {
-        let out = Command::new(&self.command)
-            .current_dir(self.dir())
-            .arg("-C")
-            .arg(self.dir())
-            .args(args)
-            .output()
-            .map_err(Error::CommandCannotRun)?;
+        // Note: All git commands run by this library are read-only. `--no-optional-locks` (git
+        // 2.15+) avoids taking locks like index.lock which conflict with concurrent git processes
+        // run by IDEs. `--version` is excluded since it is necessary to check the version.
+        let is_version = args.first().map(|a| a.as_ref() == "--version") == Some(true);
+        let no_optional_locks =
+            !is_version && self.version().map(|v| v >= (2, 15, 0)).unwrap_or(false);
+
+        if self.on_debug.is_some() {
+            let mut msg = format!("Running `{} -C {:?}", self.command, self.dir);
+            if let Some(git_dir) = &self.git_dir {
+                msg.push_str(&format!(" --git-dir {:?}", git_dir));
+            }
+            if no_optional_locks {
+                msg.push_str(" --no-optional-locks");
+            }
+            for arg in args.iter() {
+                msg.push_str(&format!(" {:?}", arg));
+            }
+            msg.push('`');
+            self.debug(&msg);
+        }
+
+        let mut cmd = Command::new(self.command);
+        // Note: Run git with fixed locale so that parsing its output does not depend on user's
+        // environment. System-wide config is also ignored for reproducible results.
+        cmd.env("LC_ALL", "C").env("GIT_CONFIG_NOSYSTEM", "1");
+        // Environment variables given by the caller take precedence over the above defaults
+        cmd.envs(self.envs.iter().map(|(k, v)| (k, v)));
+        cmd.arg("-C").arg(&self.dir);
+        if let Some(git_dir) = &self.git_dir {
+            cmd.arg("--git-dir").arg(git_dir);
+        }
+        if no_optional_locks {
+            cmd.arg("--no-optional-locks");
+        }
+        cmd.args(args);
+
+        let args = || {
+            args.iter()
+                .map(|a| a.as_ref().to_string_lossy().to_string())
+                .collect()
+        };
+
+        let out = match self.timeout {
+            Some(timeout) => match output_with_timeout(&mut cmd, timeout) {
+                Ok(Some(out)) => out,
+                Ok(None) => {
+                    self.debug(&format!("Git command timed out after {:?}", timeout));
+                    return Err(Error::GitCommandTimedOut {
+                        args: args(),
+                        timeout,
+                    });
+                }
+                Err(err) => return Err(Error::CommandCannotRun(err)),
+            },
+            None => cmd.output().map_err(Error::CommandCannotRun)?,
+        };
 
         if out.status.success() {
-            let s = str::from_utf8(&out.stdout)
-                .expect("Failed to convert git command stdout from UTF8");
+            let s = str::from_utf8(&out.stdout).map_err(|error| Error::GitOutputNotUtf8 {
+                args: args(),
+                error,
+            })?;
             Ok(s.trim().to_string())
         } else {
-            let stderr = str::from_utf8(&out.stderr)
-                .expect("Failed to convert git command stderr from UTF8")
-                .trim()
-                .to_string();
+            // Note: stderr is only for diagnostics. Broken sequences don't need to be an error.
+            let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
+            self.debug(&format!("Git command failed: {}", stderr));
+            if stderr.to_ascii_lowercase().contains("not a git repository") {
+                return Err(not_a_git_repository(&self.path, &self.dir));
+            }
             Err(Error::GitCommandFailed {
                 stderr,
-                args: args
-                    .iter()
-                    .map(|a| a.as_ref().to_string_lossy().to_string())
-                    .collect(),
+                args: args(),
             })
         }
     }
 
+    // Version of git command. It is cached in the cache shared among `Git` instances when given.
+    // When the version cannot be known, git is treated as an old version.
+    pub fn version(&self) -> Option<Version> {
+        match self.shared_version {
+            Some(cache) => *cache.get_or_init(|| self.probe_version()),
+            None => *self.version.get_or_init(|| self.probe_version()),
+        }
+    }
+
+    fn probe_version(&self) -> Option<Version> {
+        match self.command(&["--version"]) {
+            Ok(stdout) => {
+                let version = parse_version(&stdout);
+                if version.is_none() {
+                    self.debug(&format!("Cannot parse version of git: {:?}", stdout));
+                }
+                version
+            }
+            Err(err) => {
+                self.debug(&format!("Cannot get version of git: {}", err));
+                None
+            }
+        }
+    }
+
     pub fn remote_url<S: AsRef<str>>(&self, name: S) -> Result<String> {
+        let name = name.as_ref();
         // XXX:
         // `git remote get-url {name}` is not available because it's added recently (at 2.6.1).
         // Note that git installed in Ubuntu 14.04 is 1.9.1.
-        let mut url =
-            self.command(&["config", "--get", &format!("remote.{}.url", name.as_ref())])?;
-
-        if url.starts_with("git@") {
-            // Note: Convert SSH protocol URL
-            //  git@service.com:user/repo.git -> ssh://git@service.com:22/user/repo.git
-            if let Some(i) = url.find(':') {
-                url.insert_str(i + 1, "22/");
+        // `git ls-remote --get-url` is available since 1.7.5. It applies `url.<base>.insteadOf`
+        // rewrites to the URL. With `--get-url`, it only prints the URL and never connects to the
+        // remote. The version is known without an extra process since `command` checks it.
+        if self.version().map(|v| v < (1, 7, 5)).unwrap_or(true) {
+            return self.configured_remote_url(name);
+        }
+        let url = self.command(&["ls-remote", "--get-url", name])?;
+        let url = trim_url(&url);
+        if url == name {
+            // Note: `git ls-remote --get-url` outputs the given name as-is when no such remote. The
+            // remote is looked up in config to distinguish it from a remote whose URL is its name.
+            return self.configured_remote_url(name);
+        }
+        Ok(url.to_string())
+    }
+
+    // URL of the remote read from `git config` with `url.<base>.insteadOf` rewrites applied
+    fn configured_remote_url(&self, name: &str) -> Result<String> {
+        let url = match self.command(&["config", "--get", &format!("remote.{}.url", name)]) {
+            Ok(url) => url,
+            // Note: `git config --get` exits with 1 when the key is not found
+            Err(Error::GitCommandFailed { ref stderr, .. }) if stderr.is_empty() => {
+                return Err(Error::CannotDetect {
+                    reason: format!("URL of remote '{}' is not configured", name),
+                });
             }
-            url.insert_str(0, "ssh://");
+            Err(err) => return Err(err),
+        };
+        Ok(apply_instead_of(trim_url(&url), &self.instead_of_rules()?))
+    }
+
+    // Pairs of base and prefix of `url.<base>.insteadOf` in all config files
+    fn instead_of_rules(&self) -> Result<Vec<(String, String)>> {
+        match self.command(&["config", "--get-regexp", r"^url\..*\.insteadof$"]) {
+            Ok(stdout) => Ok(parse_instead_of_entries(&stdout)),
+            Err(Error::GitCommandFailed { ref stderr, .. }) if stderr.is_empty() => Ok(vec![]),
+            Err(err) => Err(err),
         }
+    }
 
-        Ok(url)
+    // Pairs of remote name and URL of all remotes read from `git config`. `url.<base>.insteadOf`
+    // rewrites are applied like `remote_url`. Rewrite rules are read by another `git config`
+    // invocation only when some remote exists since they are usually in global config.
+    pub fn all_remote_urls(&self) -> Result<Vec<(String, String)>> {
+        // Note: `--local` is necessary not to read remotes in global config outside repositories
+        let remotes =
+            match self.command(&["config", "--local", "--get-regexp", r"^remote\..*\.url$"]) {
+                Ok(stdout) => parse_remote_url_entries(&stdout),
+                // Note: `git config --get-regexp` exits with 1 when no key matches
+                Err(Error::GitCommandFailed { ref stderr, .. }) if stderr.is_empty() => vec![],
+                Err(Error::GitCommandFailed { ref stderr, .. })
+                    if stderr.contains("inside a git repository") =>
+                {
+                    return Err(not_a_git_repository(&self.path, &self.dir));
+                }
+                Err(err) => return Err(err),
+            };
+        if remotes.is_empty() {
+            return Ok(remotes);
+        }
+        let rules = self.instead_of_rules()?;
+        Ok(remotes
+            .into_iter()
+            .map(|(name, url)| {
+                let url = apply_instead_of(&url, &rules);
+                (name, url)
+            })
+            .collect())
+    }
+
+    // Remote name and branch name of the upstream of the current branch. It returns `None` when no
+    // upstream is configured.
+    pub fn upstream(&self) -> Result<Option<(String, String)>> {
+        let stdout = match self.command(&["rev-parse", "--abbrev-ref", "--symbolic", "@{u}"]) {
+            Ok(stdout) => stdout,
+            Err(Error::GitCommandFailed { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        // stdout is formatted as '{remote-name}/{branch-name}'
+        let mut entries = stdout.splitn(2, '/');
+        match (entries.next(), entries.next()) {
+            (Some(name), Some(branch)) => Ok(Some((name.to_string(), branch.to_string()))),
+            _ => Ok(None),
+        }
+    }
+
+    // Default branch of the remote read from local 'refs/remotes/{remote}/HEAD' without network
+    // access. It returns `None` when the ref is not set (e.g. the remote was added by `git remote
+    // add` without `-m`).
+    pub fn remote_default_branch_local(&self, remote: &str) -> Result<Option<String>> {
+        let head = format!("refs/remotes/{}/HEAD", remote);
+        let stdout = match self.command(&["symbolic-ref", "--quiet", &head]) {
+            Ok(stdout) => stdout,
+            Err(Error::GitCommandFailed { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let prefix = format!("refs/remotes/{}/", remote);
+        Ok(stdout.strip_prefix(&prefix).map(str::to_string))
+    }
+
+    // Check 'refs/remotes/{remote}/{branch}' exists locally. The result may be stale since network
+    // is never accessed to fetch the remote.
+    pub fn branch_exists_on_remote(&self, remote: &str, branch: &str) -> Result<bool> {
+        let r = format!("refs/remotes/{}/{}", remote, branch);
+        match self.command(&["show-ref", "--verify", "--quiet", &r]) {
+            Ok(_) => Ok(true),
+            Err(Error::GitCommandFailed { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
     }
 
     pub fn tracking_remote(&self) -> Result<(String, Option<String>)> {
-        let output = self.command(&["rev-parse", "--abbrev-ref", "--symbolic", "@{u}"]);
-        let (url, branch) = if let Ok(stdout) = output {
-            // stdout is formatted as '{remote-name}/{branch-name}'
-            let mut entries = stdout.splitn(2, '/');
-            if let (Some(ref name), branch) = (entries.next(), entries.next()) {
-                (self.remote_url(name), branch.map(str::to_string))
-            } else {
-                (self.remote_url("origin"), None)
-            }
-        } else {
-            (self.remote_url("origin"), None)
+        let (url, branch) = match self.upstream()? {
+            Some((name, branch)) => (self.remote_url(name), Some(branch)),
+            None => (self.remote_url("origin"), None),
         };
         url.map(|u| (u, branch.or_else(|| self.current_branch().ok())))
     }
 
+    // URL of the first existing remote in `names` and the current branch
+    pub fn preferred_remote(&self, names: &[String]) -> Result<(String, Option<String>)> {
+        let remotes = self.all_remote_urls()?;
+        let name = names
+            .iter()
+            .find(|n| remotes.iter().any(|(r, _)| r == *n))
+            .ok_or_else(|| Error::CannotDetect {
+                reason: format!("None of remotes {} exists", names.join(", ")),
+            })?;
+        let url = self.remote_url(name)?;
+        Ok((url, self.current_branch().ok()))
+    }
+
+    pub fn branches(&self) -> Result<Vec<BranchInfo>> {
+        self.command(&["for-each-ref", BRANCH_FORMAT, "refs/heads"])
+            .map(|stdout| parse_branches(&stdout))
+    }
+
+    pub fn toplevel(&self) -> Result<PathBuf> {
+        self.command(&["rev-parse", "--show-toplevel"])
+            .map(PathBuf::from)
+    }
+
+    // Path of the file relative to the root directory of the repository
+    pub fn relative_path(&self, file: &Path) -> Result<PathBuf> {
+        let root = self.toplevel()?;
+        let file = absolute(file);
+        // Note: Path from git is a real path. Symbolic links in the file path must be resolved
+        let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+        match canonical(&file).strip_prefix(canonical(&root)) {
+            Ok(rel) => Ok(rel.to_path_buf()),
+            Err(_) => Err(Error::CannotDetect {
+                reason: format!(
+                    "File {} is not in repository {}",
+                    file.display(),
+                    root.display()
+                ),
+            }),
+        }
+    }
+
+    // Shallow clone may not have the history of commits which permalinks point to
+    pub fn is_shallow(&self) -> Result<bool> {
+        // Note: `git rev-parse --is-shallow-repository` is available since 2.15.0
+        if self.version().map(|v| v >= (2, 15, 0)).unwrap_or(false) {
+            return self
+                .command(&["rev-parse", "--is-shallow-repository"])
+                .map(|out| out == "true");
+        }
+        let git_dir = PathBuf::from(self.command(&["rev-parse", "--git-dir"])?);
+        Ok(self.dir.join(git_dir).join("shallow").is_file())
+    }
+
+    // Nearest tag reachable from HEAD with offset like 'v1.2.3-4-gabcdef'. When no tag is
+    // reachable, abbreviated commit SHA is returned thanks to '--always'
+    pub fn describe(&self) -> Result<String> {
+        self.command(&["describe", "--tags", "--always"])
+    }
+
     pub fn current_branch(&self) -> Result<String> {
         self.command(&["rev-parse", "--abbrev-ref", "--symbolic", "HEAD"])
     }
 }
 
 impl<'a> Git<'a> {
-    pub fn new<P: AsRef<Path>>(path: &'a P, git_cmd: Option<&'a str>) -> Git<'a> {
+    pub fn new<P: AsRef<Path>>(path: P, git_cmd: Option<&'a str>) -> Git<'a> {
         Git {
             command: git_cmd.unwrap_or("git"),
-            path: path.as_ref(),
+            path: path.as_ref().to_path_buf(),
+            dir: resolve_dir(path.as_ref()),
+            git_dir: None,
+            version: OnceCell::new(),
+            shared_version: None,
+            envs: &[],
+            timeout: None,
+            on_debug: None,
         }
     }
+
+    // Note: Relative Git directory is resolved from the current directory, not from `-C` path
+    pub fn git_dir(mut self, dir: Option<&Path>) -> Git<'a> {
+        self.git_dir = dir.map(absolute);
+        self
+    }
+
+    pub fn envs(mut self, envs: &'a [(String, String)]) -> Git<'a> {
+        self.envs = envs;
+        self
+    }
+
+    pub fn version_cache(mut self, cache: &'a OnceLock<Option<Version>>) -> Git<'a> {
+        self.shared_version = Some(cache);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Git<'a> {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn on_debug(mut self, f: Option<&'a dyn Fn(&str)>) -> Git<'a> {
+        self.on_debug = f;
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(unix)]
+    use crate::testing::fake_git;
+    use crate::testing::{commit, git_repo, run_git, set_upstream, temp_dir};
+    #[cfg(unix)]
+    use std::fs;
 
     #[test]
     fn tracking_remote() {
         let p = Path::new(".");
-        let git = Git::new(&p, None);
+        let git = Git::new(p, None);
         let (url, branch) = git.tracking_remote().unwrap();
         assert!(
             url.starts_with("https://") || url.starts_with("ssh://"),
@@ -114,10 +535,325 @@ mod tests {
         assert!(branch.is_some(), "{:?}", branch);
     }
 
+    #[test]
+    fn parse_version_output() {
+        assert_eq!(parse_version("git version 2.39.2"), Some((2, 39, 2)));
+        assert_eq!(
+            parse_version("git version 2.39.2 (Apple Git-143)"),
+            Some((2, 39, 2))
+        );
+        assert_eq!(
+            parse_version("git version 2.41.0.windows.1"),
+            Some((2, 41, 0))
+        );
+        assert_eq!(parse_version("git version 1.7"), Some((1, 7, 0)));
+        assert_eq!(parse_version("hello"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remote_url_by_git_version() {
+        let script = r#"echo "$*" >> "$LOG"
+case "$*" in
+    *--version*) echo "git version $VERSION" ;;
+    *"ls-remote --get-url"*)
+        case "$*" in
+            *origin*) echo 'https://github.com/rhysd/ls-remote.git' ;;
+            *) echo "${*##* }" ;;
+        esac ;;
+    *"config --get remote.origin.url"*) echo 'gh:rhysd/config.git' ;;
+    *"config --get remote.self.url"*) echo 'self' ;;
+    *"config --get remote."*) exit 1 ;;
+    *"config --get-regexp"*) echo 'url.https://github.com/.insteadof gh:' ;;
+esac"#;
+        for (version, expected) in &[("1.7.4", "config"), ("1.7.5", "ls-remote")] {
+            let name = format!("remote_url_by_git_version_{}", version);
+            let log = temp_dir(&format!("{}_log", name)).join("log");
+            let script = format!("VERSION={}\nLOG='{}'\n{}", version, log.display(), script);
+            let cmd = fake_git(&name, &script);
+            let git = Git::new(Path::new("."), Some(cmd.to_str().unwrap()));
+            let url = git.remote_url("origin").unwrap();
+            assert_eq!(url, format!("https://github.com/rhysd/{}.git", expected));
+            let ran = fs::read_to_string(&log).unwrap();
+            let used_ls_remote = ran.contains("ls-remote --get-url origin");
+            let used_config = ran.contains("config --get remote.origin.url");
+            assert_eq!(used_ls_remote, *expected == "ls-remote", "{}", ran);
+            assert_eq!(used_config, *expected == "config", "{}", ran);
+
+            // Remote whose URL is the same as its name is not an unknown remote
+            assert_eq!(git.remote_url("self").unwrap(), "self");
+
+            let err = git.remote_url("unknown").unwrap_err();
+            assert!(format!("{}", err).contains("not configured"), "{}", err);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_timeout() {
+        let script = r#"case "$*" in
+    *--version*) echo 'git version 2.39.2' ;;
+    *"rev-parse --abbrev-ref"*) sleep 10 ;;
+    *describe*) echo 'v1.2.3' ;;
+esac"#;
+        let cmd = fake_git("command_timeout", script);
+        let git = Git::new(Path::new("."), Some(cmd.to_str().unwrap()))
+            .timeout(Some(Duration::from_millis(500)));
+        assert_eq!(git.describe().unwrap(), "v1.2.3");
+
+        let start = Instant::now();
+        match git.current_branch().unwrap_err() {
+            Error::GitCommandTimedOut { timeout, .. } => {
+                assert_eq!(timeout, Duration::from_millis(500))
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn no_optional_locks_by_git_version() {
+        let script = r#"case "$*" in
+    *--version*) echo "git version $VERSION" ;;
+    *"--no-optional-locks rev-parse"*) echo 'without-locks' ;;
+    *rev-parse*) echo 'with-locks' ;;
+esac"#;
+        for (version, expected) in &[
+            ("2.14.6", "with-locks"),
+            ("2.15.0", "without-locks"),
+            ("2.39.2", "without-locks"),
+        ] {
+            let name = format!("no_optional_locks_{}", version);
+            let cmd = fake_git(&name, &format!("VERSION={}\n{}", version, script));
+            let git = Git::new(Path::new("."), Some(cmd.to_str().unwrap()));
+            assert_eq!(git.current_branch().unwrap(), *expected, "{}", version);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn describe_output() {
+        let cmd = fake_git(
+            "describe_output",
+            r#"case "$*" in
+    *"describe --tags --always"*) echo 'v1.2.3-4-gabcdef' ;;
+    *) exit 1 ;;
+esac"#,
+        );
+        let git = Git::new(Path::new("."), Some(cmd.to_str().unwrap()));
+        assert_eq!(git.describe().unwrap(), "v1.2.3-4-gabcdef");
+    }
+
+    #[test]
+    fn quoted_remote_url() {
+        let dir = git_repo("git-quoted-remote-url", &[]);
+        run_git(
+            &dir,
+            &[
+                "config",
+                "remote.origin.url",
+                " \"https://github.com/rhysd/detect_git_service.git\" ",
+            ],
+        );
+        let git = Git::new(&dir, None);
+        assert_eq!(
+            git.remote_url("origin").unwrap(),
+            "https://github.com/rhysd/detect_git_service.git"
+        );
+    }
+
+    #[test]
+    fn remote_default_branch() {
+        let dir = git_repo(
+            "git-remote-default-branch",
+            &[("origin", "https://github.com/rhysd/detect_git_service.git")],
+        );
+        let git = Git::new(&dir, None);
+        assert_eq!(git.remote_default_branch_local("origin").unwrap(), None);
+
+        run_git(
+            &dir,
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/develop",
+            ],
+        );
+        assert_eq!(
+            git.remote_default_branch_local("origin").unwrap(),
+            Some("develop".to_string())
+        );
+        assert_eq!(git.remote_default_branch_local("upstream").unwrap(), None);
+
+        let outside = Git::new(temp_dir("git-remote-default-branch-outside"), None);
+        match outside.remote_default_branch_local("origin").unwrap_err() {
+            Error::NotAGitRepository { .. } => {}
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn remote_branch_existence() {
+        let dir = git_repo(
+            "git-branch-exists-on-remote",
+            &[("origin", "https://github.com/rhysd/detect_git_service.git")],
+        );
+        commit(&dir);
+        let git = Git::new(&dir, None);
+        assert!(!git.branch_exists_on_remote("origin", "main").unwrap());
+
+        run_git(&dir, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+        assert!(git.branch_exists_on_remote("origin", "main").unwrap());
+        assert!(!git.branch_exists_on_remote("origin", "feature").unwrap());
+        assert!(!git.branch_exists_on_remote("upstream", "main").unwrap());
+
+        let outside = Git::new(temp_dir("git-branch-exists-on-remote-outside"), None);
+        match outside
+            .branch_exists_on_remote("origin", "main")
+            .unwrap_err()
+        {
+            Error::NotAGitRepository { .. } => {}
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn describe_tags() {
+        let dir = git_repo("git-describe", &[]);
+        commit(&dir);
+        let git = Git::new(&dir, None);
+        let sha = git.command(&["rev-parse", "--short", "HEAD"]).unwrap();
+        assert_eq!(git.describe().unwrap(), sha);
+
+        run_git(&dir, &["tag", "v1.0.0"]);
+        assert_eq!(git.describe().unwrap(), "v1.0.0");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn injected_envs() {
+        let cmd = fake_git(
+            "injected_envs",
+            r#"case "$*" in
+    *--version*) echo 'git version 2.39.2' ;;
+    *"ls-remote --get-url origin"*) echo "https://github.com/rhysd/$REPO_NAME.git" ;;
+esac"#,
+        );
+        let envs = vec![("REPO_NAME".to_string(), "injected".to_string())];
+        let git = Git::new(Path::new("."), Some(cmd.to_str().unwrap())).envs(&envs);
+        assert_eq!(
+            git.remote_url("origin").unwrap(),
+            "https://github.com/rhysd/injected.git"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn is_shallow_by_git_version() {
+        let git_dir = temp_dir("is_shallow_git_dir");
+        let script = format!(
+            r#"case "$*" in
+    *--version*) echo "git version $VERSION" ;;
+    *--is-shallow-repository*) echo "$SHALLOW" ;;
+    *--git-dir*) echo '{}' ;;
+esac"#,
+            git_dir.display(),
+        );
+        for (version, shallow) in &[
+            ("2.39.2", true),
+            ("2.39.2", false),
+            ("2.14.0", true),
+            ("2.14.0", false),
+        ] {
+            let name = format!("is_shallow_{}_{}", version, shallow);
+            let script = format!("VERSION={}\nSHALLOW={}\n{}", version, shallow, script);
+            let cmd = fake_git(&name, &script);
+            let shallow_file = git_dir.join("shallow");
+            if *shallow {
+                std::fs::write(&shallow_file, "").unwrap();
+            } else if shallow_file.exists() {
+                std::fs::remove_file(&shallow_file).unwrap();
+            }
+            let git = Git::new(Path::new("."), Some(cmd.to_str().unwrap()));
+            assert_eq!(git.is_shallow().unwrap(), *shallow, "{}", version);
+        }
+    }
+
+    #[test]
+    fn parse_branches_output() {
+        let stdout = "feature/foo\0origin/feature/foo\0 \nmain\0origin/main\0*\nwip\0\0";
+        assert_eq!(
+            parse_branches(stdout),
+            vec![
+                BranchInfo {
+                    name: "feature/foo".to_string(),
+                    upstream: Some("origin/feature/foo".to_string()),
+                    is_current: false,
+                },
+                BranchInfo {
+                    name: "main".to_string(),
+                    upstream: Some("origin/main".to_string()),
+                    is_current: true,
+                },
+                BranchInfo {
+                    name: "wip".to_string(),
+                    upstream: None,
+                    is_current: false,
+                },
+            ],
+        );
+        assert_eq!(parse_branches(""), vec![]);
+    }
+
+    #[test]
+    fn branches() {
+        let dir = git_repo(
+            "git-branches",
+            &[("origin", "https://github.com/rhysd/detect_git_service.git")],
+        );
+        commit(&dir);
+        set_upstream(&dir, "origin", "main");
+        run_git(&dir, &["branch", "wip"]);
+        let branches = Git::new(&dir, None).branches().unwrap();
+        assert_eq!(
+            branches,
+            vec![
+                BranchInfo {
+                    name: "main".to_string(),
+                    upstream: Some("origin/main".to_string()),
+                    is_current: true,
+                },
+                BranchInfo {
+                    name: "wip".to_string(),
+                    upstream: None,
+                    is_current: false,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn upstream() {
+        let dir = git_repo(
+            "git-upstream",
+            &[("origin", "https://github.com/rhysd/detect_git_service.git")],
+        );
+        commit(&dir);
+        let git = Git::new(&dir, None);
+        assert_eq!(git.upstream().unwrap(), None);
+
+        set_upstream(&dir, "origin", "feature/foo");
+        assert_eq!(
+            git.upstream().unwrap(),
+            Some(("origin".to_string(), "feature/foo".to_string()))
+        );
+    }
+
     #[test]
     fn remote_url() {
         let p = Path::new(".");
-        let git = Git::new(&p, None);
+        let git = Git::new(p, None);
         let url = git.remote_url("origin").unwrap();
         assert!(
             url.starts_with("https://") || url.starts_with("ssh://"),
@@ -126,4 +862,247 @@ mod tests {
         );
         assert!(url.contains("detect_git_service"), "{}", url);
     }
+
+    #[test]
+    fn parse_instead_of_entries_output() {
+        let stdout = "url.https://github.com/.insteadof gh:\n\
+                      url.git@gitlab.com:.insteadof https://gitlab.com/\n\
+                      url.https://example.com/with space/.insteadof ex:\n";
+        assert_eq!(
+            parse_instead_of_entries(stdout),
+            vec![
+                ("https://github.com/".to_string(), "gh:".to_string()),
+                (
+                    "git@gitlab.com:".to_string(),
+                    "https://gitlab.com/".to_string()
+                ),
+                (
+                    "https://example.com/with space/".to_string(),
+                    "ex:".to_string()
+                ),
+            ],
+        );
+        assert_eq!(parse_instead_of_entries(""), vec![]);
+    }
+
+    #[test]
+    fn parse_remote_url_entries_output() {
+        let stdout = "remote.origin.url https://github.com/rhysd/detect_git_service.git\n\
+                      remote.my.fork.url git@gitlab.com:Linda_pp/detect_git_service.git\n\
+                      remote.quoted.url  \"https://bitbucket.org/rhysd/detect_git_service\"\n\
+                      remote.origin.pushurl https://github.com/rhysd/push_only.git\n";
+        assert_eq!(
+            parse_remote_url_entries(stdout),
+            vec![
+                (
+                    "origin".to_string(),
+                    "https://github.com/rhysd/detect_git_service.git".to_string()
+                ),
+                (
+                    "my.fork".to_string(),
+                    "git@gitlab.com:Linda_pp/detect_git_service.git".to_string()
+                ),
+                (
+                    "quoted".to_string(),
+                    "https://bitbucket.org/rhysd/detect_git_service".to_string()
+                ),
+            ],
+        );
+        assert_eq!(parse_remote_url_entries(""), vec![]);
+    }
+
+    #[test]
+    fn all_remote_urls() {
+        let dir = git_repo(
+            "git-all-remote-urls",
+            &[
+                ("origin", "https://github.com/rhysd/detect_git_service.git"),
+                (
+                    "upstream",
+                    "https://gitlab.com/Linda_pp/detect_git_service.git",
+                ),
+            ],
+        );
+        let remotes = Git::new(&dir, None).all_remote_urls().unwrap();
+        assert_eq!(
+            remotes,
+            vec![
+                (
+                    "origin".to_string(),
+                    "https://github.com/rhysd/detect_git_service.git".to_string()
+                ),
+                (
+                    "upstream".to_string(),
+                    "https://gitlab.com/Linda_pp/detect_git_service.git".to_string()
+                ),
+            ],
+        );
+
+        run_git(
+            &dir,
+            &["config", "url.https://bitbucket.org/.insteadOf", "bb:"],
+        );
+        run_git(
+            &dir,
+            &["remote", "add", "mirror", "bb:rhysd/detect_git_service"],
+        );
+        let remotes = Git::new(&dir, None).all_remote_urls().unwrap();
+        assert_eq!(
+            remotes[2],
+            (
+                "mirror".to_string(),
+                "https://bitbucket.org/rhysd/detect_git_service".to_string()
+            ),
+        );
+
+        let empty = git_repo("git-all-remote-urls-empty", &[]);
+        assert_eq!(Git::new(&empty, None).all_remote_urls().unwrap(), vec![]);
+
+        let outside = Git::new(temp_dir("git-all-remote-urls-outside"), None);
+        match outside.all_remote_urls().unwrap_err() {
+            Error::NotAGitRepository { .. } => {}
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn relative_path_of_file() {
+        let cwd = env::current_dir().unwrap();
+        for (file, expected) in &[
+            (cwd.join("src").join("lib.rs"), Path::new("src/lib.rs")),
+            (cwd.join("Cargo.toml"), Path::new("Cargo.toml")),
+            (PathBuf::from("src/git.rs"), Path::new("src/git.rs")),
+        ] {
+            let git = Git::new(file, None);
+            assert_eq!(git.relative_path(file).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn nonexistent_path() {
+        let cwd = env::current_dir().unwrap();
+        let git = Git::new(cwd.join("src").join("foo").join("bar.rs"), None);
+        assert_eq!(git.dir, cwd.join("src"));
+    }
+
+    #[test]
+    fn relative_path() {
+        for p in &[
+            Path::new("src"),
+            Path::new("src/lib.rs"),
+            Path::new("LICENSE"),
+        ] {
+            let git = Git::new(p, None);
+            assert!(git.dir.is_absolute(), "{:?}", git.dir);
+            let top = git.command(&["rev-parse", "--show-toplevel"]).unwrap();
+            let cwd = env::current_dir().unwrap();
+            assert_eq!(
+                Path::new(&top).canonicalize().unwrap(),
+                cwd.canonicalize().unwrap()
+            );
+        }
+        let git = Git::new(Path::new("src"), None);
+        assert!(git.dir.ends_with("src"), "{:?}", git.dir);
+        let prefix = git.command(&["rev-parse", "--show-prefix"]).unwrap();
+        assert_eq!(prefix, "src/");
+    }
+
+    #[test]
+    fn explicit_git_dir() {
+        let dir = temp_dir("git-explicit-git-dir");
+        let git = Git::new(&dir, None).git_dir(Some(Path::new(".git")));
+        let url = git.remote_url("origin").unwrap();
+        assert!(url.contains("detect_git_service"), "{}", url);
+    }
+
+    #[test]
+    fn not_a_git_repository() {
+        let dir = temp_dir("git-not-a-repository");
+        let git = Git::new(&dir, None);
+        for err in &[
+            git.tracking_remote().unwrap_err(),
+            git.current_branch().unwrap_err(),
+        ] {
+            match err {
+                Error::NotAGitRepository { path } => assert_eq!(path, &dir),
+                _ => panic!("unexpected error: {:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn not_a_git_repository_reports_given_path() {
+        let dir = temp_dir("git-not-a-repository-given-path");
+        let file = dir.join("not").join("existing").join("file.rs");
+        let err = Git::new(&file, None).current_branch().unwrap_err();
+        match err {
+            Error::NotAGitRepository { ref path } => assert_eq!(path, &file),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn pijul_repository() {
+        let dir = temp_dir("git-pijul-repository");
+        std::fs::create_dir_all(dir.join(".pijul")).unwrap();
+        let err = Git::new(&dir, None).tracking_remote().unwrap_err();
+        match err {
+            Error::CannotDetect { ref reason } => {
+                assert!(reason.contains("in Pijul repository"), "{}", reason)
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_stderr() {
+        let cmd = fake_git(
+            "invalid_utf8_stderr",
+            r"printf 'fatal: \377\376 broken' >&2; exit 1",
+        );
+        let cmd = cmd.to_str().unwrap();
+        let p = Path::new(".");
+        let git = Git::new(p, Some(cmd));
+        let err = git.command(&["status"]).unwrap_err();
+        match err {
+            Error::GitCommandFailed { ref stderr, .. } => {
+                assert!(stderr.starts_with("fatal: "), "{}", stderr);
+                assert!(stderr.contains('\u{FFFD}'), "{}", stderr);
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+        let msg = format!("{}", err);
+        assert!(
+            msg.contains("`git 'status'` exited with non-zero status"),
+            "{}",
+            msg
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn locale_independent_env() {
+        let cmd = fake_git(
+            "locale_independent_env",
+            r#"printf '%s %s' "$LC_ALL" "$GIT_CONFIG_NOSYSTEM""#,
+        );
+        let cmd = cmd.to_str().unwrap();
+        let git = Git::new(Path::new("."), Some(cmd));
+        assert_eq!(git.command(&["status"]).unwrap(), "C 1");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_stdout() {
+        let cmd = fake_git("invalid_utf8_stdout", r"printf '\377\376'");
+        let cmd = cmd.to_str().unwrap();
+        let p = Path::new(".");
+        let git = Git::new(p, Some(cmd));
+        let err = git.command(&["status"]).unwrap_err();
+        match err {
+            Error::GitOutputNotUtf8 { ref args, .. } => assert_eq!(args, &["status"]),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
 } // mod tests
@@ -5,6 +5,8 @@
 //! path.
 //!
 //! ```
+//! # #[cfg(not(feature = "no_process"))]
+//! # fn main() {
 //! use std::path::Path;
 //! use detect_git_service::GitService;
 //!
@@ -15,11 +17,31 @@
 //! assert_eq!(service.repo(), "detect_git_service");
 //! assert!(service.branch().is_some());
 //!
-//! if let GitService::GitHub{user, repo, branch} = service {
+//! if let GitService::GitHub{user, repo, branch, ..} = service {
 //!     assert_eq!(user, "rhysd");
 //!     assert_eq!(repo, "detect_git_service");
 //!     assert!(branch.is_some());
 //! }
+//! # }
+//! # #[cfg(feature = "no_process")]
+//! # fn main() {}
+//! ```
+//!
+//! Detection never accesses network. `git` command is only run for reading
+//! the local repository, so SSH is never executed even when an SSH remote is
+//! configured.
+//!
+//! When `no_process` feature is enabled, only APIs to classify URLs such as
+//! `detect_from_url` and `parse_remote_url` are available. APIs which run `git`
+//! command or touch file system are excluded so that this crate can be used on
+//! targets where spawning processes is impossible such as WebAssembly.
+//!
+//! ```
+//! use detect_git_service::ServiceKind;
+//!
+//! let url = "git@github.com:rhysd/detect_git_service.git";
+//! let service = detect_git_service::detect_from_url(url).unwrap();
+//! assert_eq!(service.kind(), ServiceKind::GitHub);
 //! ```
 
 #![deny(missing_docs)]
@@ -27,9 +49,42 @@
 extern crate diff_enum;
 extern crate url;
 
+#[cfg(not(feature = "no_process"))]
+mod ci;
+#[cfg(not(feature = "no_process"))]
+mod config;
 mod error;
+#[cfg(not(feature = "no_process"))]
 mod git;
+#[cfg(not(feature = "no_process"))]
+mod manifest;
+#[cfg(not(feature = "no_process"))]
+mod options;
+mod remote;
 mod service;
+#[cfg(not(feature = "no_process"))]
+mod ssh_config;
+#[cfg(all(test, not(feature = "no_process")))]
+mod testing;
 
-pub use crate::error::Error;
-pub use crate::service::{detect, detect_with_git, GitService};
+#[cfg(not(feature = "no_process"))]
+pub use crate::ci::{detect_from_env, detect_from_env_with_url_vars};
+pub use crate::error::{Error, Result};
+#[cfg(not(feature = "no_process"))]
+pub use crate::git::BranchInfo;
+#[cfg(not(feature = "no_process"))]
+pub use crate::manifest::detect_from_manifest;
+#[cfg(not(feature = "no_process"))]
+pub use crate::options::DetectOptions;
+pub use crate::remote::{parse_remote_url, ParsedRemote, Transport};
+#[cfg(not(feature = "no_process"))]
+pub use crate::service::{
+    blob_url_for_file, branches, detect, detect_all_remotes, detect_first, detect_from_git_config,
+    detect_origin, detect_submodules, detect_with_git, detect_with_options, detect_workspace,
+    primary_and_mirrors, relative_path,
+};
+pub use crate::service::{
+    classify_host, detect_from_browser_url, detect_from_config, detect_from_go_module,
+    detect_from_reader, detect_from_url, detect_github_shorthand, Confidence, GitService,
+    OwnerKind, ServiceKind,
+};
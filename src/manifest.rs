@@ -0,0 +1,340 @@
+use crate::error::{Error, Result};
+use crate::service::{detect_with_remote_and_branch, GitService};
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+// Parse a string value of TOML. Both basic string "..." and literal string '...' are supported.
+fn parse_toml_string(src: &str) -> Option<String> {
+    let mut chars = src.chars();
+    let mut value = String::new();
+    match chars.next()? {
+        '\'' => {
+            for c in chars {
+                if c == '\'' {
+                    return Some(value);
+                }
+                value.push(c);
+            }
+            None
+        }
+        '"' => {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => return Some(value),
+                    '\\' => match chars.next()? {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        c => value.push(c),
+                    },
+                    c => value.push(c),
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+// Find `repository` field in [package] or [workspace.package] table of Cargo.toml
+fn cargo_toml_repository(src: &str) -> Option<String> {
+    let mut in_package = false;
+    for line in src.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let table = line.trim_matches(|c| c == '[' || c == ']').trim();
+            in_package = table == "package" || table == "workspace.package";
+            continue;
+        }
+        if !in_package {
+            continue;
+        }
+        let mut kv = line.splitn(2, '=');
+        if let (Some(key), Some(value)) = (kv.next(), kv.next()) {
+            if key.trim() == "repository" {
+                return parse_toml_string(value.trim());
+            }
+        }
+    }
+    None
+}
+
+// Minimal JSON value. Only strings and objects are necessary to read `repository` field.
+enum Json {
+    Str(String),
+    Object(Vec<(String, Json)>),
+    Other,
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, want: char) -> Option<()> {
+        self.skip_ws();
+        if self.chars.next()? == want {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(s),
+                '\\' => match self.chars.next()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let code = u32::from_str_radix(&hex, 16).ok()?;
+                        s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_ws();
+        match *self.chars.peek()? {
+            '"' => self.string().map(Json::Str),
+            '{' => {
+                self.chars.next();
+                let mut props = vec![];
+                self.skip_ws();
+                if self.chars.peek() == Some(&'}') {
+                    self.chars.next();
+                    return Some(Json::Object(props));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.eat(':')?;
+                    props.push((key, self.value()?));
+                    self.skip_ws();
+                    match self.chars.next()? {
+                        ',' => continue,
+                        '}' => return Some(Json::Object(props)),
+                        _ => return None,
+                    }
+                }
+            }
+            '[' => {
+                self.chars.next();
+                self.skip_ws();
+                if self.chars.peek() == Some(&']') {
+                    self.chars.next();
+                    return Some(Json::Other);
+                }
+                loop {
+                    self.value()?;
+                    self.skip_ws();
+                    match self.chars.next()? {
+                        ',' => continue,
+                        ']' => return Some(Json::Other),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => {
+                // Numbers, true, false and null
+                while let Some(c) = self.chars.peek() {
+                    if *c == ',' || *c == '}' || *c == ']' || c.is_whitespace() {
+                        break;
+                    }
+                    self.chars.next();
+                }
+                Some(Json::Other)
+            }
+        }
+    }
+}
+
+fn get<'a>(props: &'a [(String, Json)], key: &str) -> Option<&'a Json> {
+    props.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+// Find `repository` field of package.json. It is a string or an object with `url` field.
+fn package_json_repository(src: &str) -> Option<String> {
+    let mut parser = JsonParser {
+        chars: src.chars().peekable(),
+    };
+    let props = match parser.value()? {
+        Json::Object(props) => props,
+        _ => return None,
+    };
+    match get(&props, "repository")? {
+        Json::Str(s) => Some(s.clone()),
+        Json::Object(repo) => match get(repo, "url")? {
+            Json::Str(s) => Some(s.clone()),
+            _ => None,
+        },
+        Json::Other => None,
+    }
+}
+
+// Expand shorthands of npm like 'github:user/repo' or 'user/repo'
+fn expand_npm_shorthand(repo: String) -> String {
+    for (prefix, host) in &[
+        ("github:", "github.com"),
+        ("gitlab:", "gitlab.com"),
+        ("bitbucket:", "bitbucket.org"),
+    ] {
+        if let Some(path) = repo.strip_prefix(prefix) {
+            return format!("https://{}/{}", host, path);
+        }
+    }
+    if !repo.contains(':') && repo.matches('/').count() == 1 {
+        return format!("https://github.com/{}", repo);
+    }
+    repo
+}
+
+/// Detect Git hosting service from repository URL declared in a package
+/// manifest file. `repository` field of `Cargo.toml` and `package.json` are
+/// supported. Git repository is not necessary. Branch is always `None`.
+pub fn detect_from_manifest<P: AsRef<Path>>(path: P) -> Result<GitService> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path).map_err(|error| Error::CannotReadFile {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let url = match path.file_name().and_then(|n| n.to_str()) {
+        Some("Cargo.toml") => cargo_toml_repository(&src),
+        Some("package.json") => package_json_repository(&src).map(expand_npm_shorthand),
+        _ => {
+            return Err(Error::CannotDetect {
+                reason: format!("Unsupported manifest file {}", path.display()),
+            });
+        }
+    };
+
+    match url {
+        Some(url) => detect_with_remote_and_branch(url, None),
+        None => Err(Error::CannotDetect {
+            reason: format!("No repository URL found in {}", path.display()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ServiceKind;
+    use crate::testing::temp_dir;
+
+    fn detect_manifest(name: &str, file: &str, content: &str) -> Result<GitService> {
+        let path = temp_dir(name).join(file);
+        fs::write(&path, content).unwrap();
+        detect_from_manifest(&path)
+    }
+
+    #[test]
+    fn cargo_toml_of_this_crate() {
+        let service = detect_from_manifest("Cargo.toml").unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "detect_git_service");
+    }
+
+    #[test]
+    fn cargo_toml() {
+        let service = detect_manifest(
+            "manifest-cargo-toml",
+            "Cargo.toml",
+            r#"
+[package]
+name = "foo"
+version = "0.1.0"
+repository = 'https://gitlab.com/Linda_pp/foo'
+
+[dependencies]
+repository = "https://github.com/rhysd/wrong"
+"#,
+        )
+        .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitLab);
+        assert_eq!(service.user(), "Linda_pp");
+        assert_eq!(service.repo(), "foo");
+        assert_eq!(service.branch(), &None);
+    }
+
+    #[test]
+    fn package_json() {
+        let cases = &[
+            (
+                r#"{"name": "foo", "repository": "https://github.com/rhysd/foo.git"}"#,
+                ServiceKind::GitHub,
+            ),
+            (
+                r#"{
+                    "name": "foo",
+                    "keywords": ["a", {"b": null}],
+                    "version": 1.0,
+                    "repository": {
+                        "type": "git",
+                        "url": "git+https://gitlab.com/rhysd/foo.git"
+                    }
+                }"#,
+                ServiceKind::GitLab,
+            ),
+            (
+                r#"{"repository": "bitbucket:rhysd/foo"}"#,
+                ServiceKind::Bitbucket,
+            ),
+            (r#"{"repository": "rhysd/foo"}"#, ServiceKind::GitHub),
+        ];
+        for (i, (json, kind)) in cases.iter().enumerate() {
+            let name = format!("manifest-package-json-{}", i);
+            let service = detect_manifest(&name, "package.json", json).unwrap();
+            assert_eq!(service.kind(), *kind, "{}", json);
+            assert_eq!(service.user(), "rhysd", "{}", json);
+            assert_eq!(service.repo(), "foo", "{}", json);
+        }
+    }
+
+    #[test]
+    fn no_repository_field() {
+        let err = detect_manifest(
+            "manifest-no-repository",
+            "package.json",
+            r#"{"name": "foo"}"#,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{}", err).contains("No repository URL found"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn unsupported_manifest() {
+        let err = detect_manifest("manifest-unsupported", "setup.py", "").unwrap_err();
+        assert!(
+            format!("{}", err).contains("Unsupported manifest file"),
+            "{}",
+            err
+        );
+    }
+} // mod tests
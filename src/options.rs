@@ -0,0 +1,1612 @@
+use crate::config::{Config, Repository};
+use crate::error::{Error, Result};
+use crate::git::{BranchInfo, Git, Version};
+use crate::remote::{
+    normalize_host, normalize_scp_like, parse_remote_url, resolve_relative_url, with_https_scheme,
+    ParsedRemote, Transport,
+};
+use crate::service::{
+    classify_host, classify_remote, detect_with_kind, github_shorthand_url, go_module_url,
+    Confidence, GitService, PathRules, ServiceKind,
+};
+use crate::ssh_config;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use url::Url;
+
+// Wrapper of callback to implement `Debug`
+#[derive(Clone)]
+struct DebugCallback(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl fmt::Debug for DebugCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("DebugCallback")
+    }
+}
+
+type ClassifierFn = dyn Fn(&ParsedRemote) -> Option<GitService> + Send + Sync;
+
+// Wrapper of custom classifier to implement `Debug`
+#[derive(Clone)]
+struct CustomClassifier(Arc<ClassifierFn>);
+
+impl fmt::Debug for CustomClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CustomClassifier")
+    }
+}
+
+/// Options to customize detection of Git hosting service.
+///
+/// ```
+/// use detect_git_service::DetectOptions;
+///
+/// let service = DetectOptions::new()
+///     .git_command("git")
+///     .normalize_ssh(false)
+///     .detect(".")
+///     .unwrap();
+///
+/// assert_eq!(service.repo(), "detect_git_service");
+/// ```
+#[derive(Debug, Clone)]
+pub struct DetectOptions {
+    git_command: Option<String>,
+    git_dir: Option<PathBuf>,
+    normalize_ssh: bool,
+    strip_git_suffix: bool,
+    accept_url: bool,
+    accept_schemeless_url: bool,
+    fallback_to_config: bool,
+    allowed_services: Option<Vec<ServiceKind>>,
+    resolve_ssh_aliases: bool,
+    ssh_config_path: Option<PathBuf>,
+    host_mappings: Vec<(String, ServiceKind)>,
+    scheme_mappings: Vec<(String, String)>,
+    remote_precedence: Option<Vec<String>>,
+    base_paths: Vec<(String, String)>,
+    default_branch: Option<String>,
+    envs: Vec<(String, String)>,
+    github_host: String,
+    custom_classifier: Option<CustomClassifier>,
+    scan_nested_repositories: bool,
+    max_scan_depth: Option<usize>,
+    timeout: Option<Duration>,
+    // Version of git command cached among `Git` instances not to run `git --version` repeatedly
+    git_version: OnceLock<Option<Version>>,
+    on_debug: Option<DebugCallback>,
+}
+
+// Collect directories containing `.git` under `dir` in sorted order. Directories of found
+// repositories are not descended into unless `nested` is `true`. `depth` is the remaining number of
+// levels to descend. Symbolic links are not followed to avoid infinite loops.
+fn find_repositories(dir: &Path, nested: bool, depth: Option<usize>, found: &mut Vec<PathBuf>) {
+    if dir.join(".git").exists() {
+        found.push(dir.to_path_buf());
+        if !nested {
+            return;
+        }
+    }
+    let depth = match depth {
+        Some(0) => return,
+        Some(d) => Some(d - 1),
+        None => None,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return, // Unreadable directory is skipped
+    };
+    let mut children: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false) && e.file_name() != ".git")
+        .map(|e| e.path())
+        .collect();
+    children.sort();
+    for child in children {
+        find_repositories(&child, nested, depth, found);
+    }
+}
+
+impl Default for DetectOptions {
+    fn default() -> DetectOptions {
+        DetectOptions {
+            git_command: None,
+            git_dir: None,
+            normalize_ssh: true,
+            strip_git_suffix: true,
+            accept_url: false,
+            accept_schemeless_url: false,
+            fallback_to_config: false,
+            allowed_services: None,
+            resolve_ssh_aliases: false,
+            ssh_config_path: None,
+            host_mappings: vec![],
+            scheme_mappings: vec![],
+            remote_precedence: None,
+            base_paths: vec![],
+            default_branch: None,
+            envs: vec![],
+            github_host: "github.com".to_string(),
+            custom_classifier: None,
+            scan_nested_repositories: false,
+            max_scan_depth: None,
+            timeout: None,
+            git_version: OnceLock::new(),
+            on_debug: None,
+        }
+    }
+}
+
+impl DetectOptions {
+    /// Create options with default values.
+    pub fn new() -> DetectOptions {
+        DetectOptions::default()
+    }
+
+    /// Git command to run. Default value is `git`.
+    pub fn git_command<S: Into<String>>(mut self, cmd: S) -> DetectOptions {
+        self.git_command = Some(cmd.into());
+        self.git_version = OnceLock::new();
+        self
+    }
+
+    /// Git directory passed to `git` command via `--git-dir` option. It is
+    /// useful for repositories whose Git directory is separated from the
+    /// working tree like `GIT_DIR`. Relative path is resolved from the current
+    /// directory. By default Git directory is looked up from the given path.
+    pub fn git_dir<P: Into<PathBuf>>(mut self, dir: P) -> DetectOptions {
+        self.git_dir = Some(dir.into());
+        self
+    }
+
+    /// Whether to rewrite scp-like remote URL `git@host:path` into SSH URL
+    /// `ssh://git@host/path` before parsing it. When `false`, the original
+    /// remote URL is parsed as-is and preserved in `ParsedRemote::url` passed
+    /// to `custom_classifier` and in debug messages. Detected service is the
+    /// same in both cases. Default value is `true`.
+    pub fn normalize_ssh(mut self, yes: bool) -> DetectOptions {
+        self.normalize_ssh = yes;
+        self
+    }
+
+    /// Whether to remove `.git` suffix from repository name. Some services allow
+    /// repository names ending with `.git`. When `false`, repository name is
+    /// kept as-is. Default value is `true`.
+    pub fn strip_git_suffix(mut self, yes: bool) -> DetectOptions {
+        self.strip_git_suffix = yes;
+        self
+    }
+
+    /// Whether to accept a remote URL like `https://github.com/user/repo` in
+    /// place of a path. When `true` and the given path looks like a URL with
+    /// scheme, the service is detected from the URL directly without running
+    /// `git` command. Default value is `false`.
+    pub fn accept_url(mut self, yes: bool) -> DetectOptions {
+        self.accept_url = yes;
+        self
+    }
+
+    /// Whether to accept a URL without scheme like `github.com/user/repo` in
+    /// place of a path. `https://` is prepended to it. Since such input is
+    /// ambiguous with a relative path, it is only treated as URL when no file
+    /// exists at the path. Default value is `false`.
+    pub fn accept_schemeless_url(mut self, yes: bool) -> DetectOptions {
+        self.accept_schemeless_url = yes;
+        self
+    }
+
+    /// Whether to read Git config file of the repository directly when `git`
+    /// command is not found. It is useful for minimal environments where Git
+    /// is not installed. See `detect_from_git_config` for the limitations.
+    /// Default value is `false`.
+    pub fn fallback_to_config(mut self, yes: bool) -> DetectOptions {
+        self.fallback_to_config = yes;
+        self
+    }
+
+    /// Kinds of services which are allowed to be detected. When a service not
+    /// in the list is detected, detection fails with `Error::CannotDetect`.
+    /// By default all services are allowed.
+    pub fn allowed_services(mut self, kinds: &[ServiceKind]) -> DetectOptions {
+        self.allowed_services = Some(kinds.to_vec());
+        self
+    }
+
+    /// Whether to resolve a host alias of SSH remote URL with `HostName` in
+    /// `Host` blocks of SSH config file (`~/.ssh/config`). For example, when
+    /// `Host myalias` maps to `HostName github.com`, remote URL
+    /// `git@myalias:user/repo.git` is detected as GitHub. Default value is `false`
+    /// since it involves file I/O.
+    pub fn resolve_ssh_aliases(mut self, yes: bool) -> DetectOptions {
+        self.resolve_ssh_aliases = yes;
+        self
+    }
+
+    /// Path to SSH config file used for resolving host aliases. Default value
+    /// is `~/.ssh/config`.
+    pub fn ssh_config_path<P: Into<PathBuf>>(mut self, path: P) -> DetectOptions {
+        self.ssh_config_path = Some(path.into());
+        self
+    }
+
+    /// Map a host name to a kind of service. It is useful for self-hosted
+    /// services whose host name does not follow the naming rules like
+    /// `github.*` or `gitlab.*`. Host name is compared case-insensitively and
+    /// mappings take precedence over the built-in rules. Service detected via a
+    /// mapping has `Confidence::Mapped`.
+    pub fn map_host<S: Into<String>>(mut self, host: S, kind: ServiceKind) -> DetectOptions {
+        self.host_mappings
+            .push((normalize_host(&host.into()), kind));
+        self
+    }
+
+    /// Rewrite scheme of remote URL before parsing it. For example,
+    /// `map_scheme("sso", "https")` makes remote URL `sso://host/user/repo`
+    /// detected as `https://host/user/repo`. It is useful for custom schemes
+    /// wrapping remotes in corporate environments. Scheme is compared
+    /// case-insensitively.
+    pub fn map_scheme<F, T>(mut self, from: F, to: T) -> DetectOptions
+    where
+        F: Into<String>,
+        T: Into<String>,
+    {
+        self.scheme_mappings
+            .push((from.into().to_ascii_lowercase(), to.into()));
+        self
+    }
+
+    /// Names of remotes in order of precedence like `["upstream", "origin"]`.
+    /// The first existing remote is used for detection instead of the remote
+    /// tracked by the current branch. It is useful for fork-heavy workflows.
+    /// By default the upstream of the current branch and then `origin` are
+    /// used.
+    pub fn remote_precedence<I, S>(mut self, names: I) -> DetectOptions
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.remote_precedence = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Path prefix where the service at the host is mounted. For example, when
+    /// Gitea is served at `https://example.com/gitea/`, calling
+    /// `base_path("example.com", "/gitea")` makes remote URL
+    /// `https://example.com/gitea/user/repo.git` detected as `user/repo`. The
+    /// base path is also reflected to URLs like `GitService::web_url`.
+    pub fn base_path<H, P>(mut self, host: H, path: P) -> DetectOptions
+    where
+        H: Into<String>,
+        P: Into<String>,
+    {
+        self.base_paths
+            .push((normalize_host(&host.into()), path.into()));
+        self
+    }
+
+    /// Environment variable passed to `git` command like `GIT_SSH_COMMAND` or
+    /// `GIT_CONFIG_GLOBAL`. It is useful for detecting a service in an isolated
+    /// environment. It can be called multiple times to add multiple variables.
+    pub fn env<K, V>(mut self, key: K, value: V) -> DetectOptions
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.envs.push((key.into(), value.into()));
+        // Note: Environment variables like `PATH` may change git command to run
+        self.git_version = OnceLock::new();
+        self
+    }
+
+    /// Branch used by URL builders like `GitService::blob_url` when the current
+    /// branch is unknown (e.g. detached HEAD). Typically `main` or `master`.
+    /// Default value is unset, which means such URLs are not built.
+    pub fn default_branch<S: Into<String>>(mut self, branch: S) -> DetectOptions {
+        self.default_branch = Some(branch.into());
+        self
+    }
+
+    /// Host of GitHub used by `detect_github_shorthand` like
+    /// `github.mycompany.com`. Default value is `github.com`.
+    pub fn github_host<S: Into<String>>(mut self, host: S) -> DetectOptions {
+        self.github_host = normalize_host(&host.into());
+        self
+    }
+
+    /// Register a fallback classifier consulted when neither the built-in rules
+    /// nor `map_host` can classify the host of remote URL. It receives the
+    /// parsed remote URL and returns the service when it knows the host. It is
+    /// useful for one-off hosts whose path layout differs from the built-in
+    /// services.
+    pub fn custom_classifier<F>(mut self, classifier: F) -> DetectOptions
+    where
+        F: Fn(&ParsedRemote) -> Option<GitService> + Send + Sync + 'static,
+    {
+        self.custom_classifier = Some(CustomClassifier(Arc::new(classifier)));
+        self
+    }
+
+    /// Whether `detect_workspace` also finds repositories nested in other
+    /// repositories such as vendored checkouts. When `false`, directories of
+    /// found repositories are not scanned. Default value is `false`.
+    pub fn scan_nested_repositories(mut self, yes: bool) -> DetectOptions {
+        self.scan_nested_repositories = yes;
+        self
+    }
+
+    /// Maximum depth of directories scanned by `detect_workspace`. Depth of
+    /// the root directory is 0 and its children are at depth 1. By default
+    /// the depth is not limited.
+    pub fn max_scan_depth(mut self, depth: usize) -> DetectOptions {
+        self.max_scan_depth = Some(depth);
+        self
+    }
+
+    /// Timeout of each `git` command run by this library. When a command does
+    /// not finish in time, it is killed and `Error::GitCommandTimedOut` is
+    /// returned. It is useful for repositories on slow network file systems.
+    /// By default commands are waited without limit.
+    pub fn timeout(mut self, timeout: Duration) -> DetectOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Register a callback to receive diagnostic messages. The callback is
+    /// called with `git` commands run by this library and decisions made
+    /// while detecting the service. It is useful for debugging detection.
+    pub fn on_debug<F>(mut self, callback: F) -> DetectOptions
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_debug = Some(DebugCallback(Arc::new(callback)));
+        self
+    }
+
+    fn debug(&self, msg: &str) {
+        if let Some(DebugCallback(f)) = &self.on_debug {
+            f(msg);
+        }
+    }
+
+    fn git(&self, path: &Path) -> Git<'_> {
+        let on_debug = self
+            .on_debug
+            .as_ref()
+            .map(|DebugCallback(f)| f.as_ref() as &dyn Fn(&str));
+        Git::new(path, self.git_command.as_deref())
+            .git_dir(self.git_dir.as_deref())
+            .envs(&self.envs)
+            .timeout(self.timeout)
+            .version_cache(&self.git_version)
+            .on_debug(on_debug)
+    }
+
+    // Check the error is caused by missing `git` command and falling back to Git config is enabled
+    fn git_not_found(&self, err: &Error) -> bool {
+        let not_found = self.fallback_to_config
+            && matches!(err, Error::CommandCannotRun(e) if e.kind() == io::ErrorKind::NotFound);
+        if not_found {
+            self.debug("Git command is not found. Reading Git config file instead");
+        }
+        not_found
+    }
+
+    fn url_in_place_of_path(&self, path: &Path) -> Option<String> {
+        let s = path.to_str()?;
+        let url = if self.accept_url && s.contains("://") && Url::parse(s).is_ok() {
+            s.to_string()
+        } else if self.accept_schemeless_url && !path.exists() {
+            with_https_scheme(s)?
+        } else {
+            return None;
+        };
+        self.debug(&format!("Input is treated as URL: {}", url));
+        Some(url)
+    }
+
+    // Rewrite scheme of the URL with the first matching mapping registered by `map_scheme`
+    fn rewrite_scheme(&self, url: String) -> String {
+        let scheme = match url.find("://") {
+            Some(i) => url[..i].to_ascii_lowercase(),
+            None => return url,
+        };
+        match self
+            .scheme_mappings
+            .iter()
+            .find(|(from, _)| *from == scheme)
+        {
+            Some((_, to)) => {
+                let rewritten = format!("{}{}", to, &url[scheme.len()..]);
+                self.debug(&format!("Rewrote URL {} to {}", url, rewritten));
+                rewritten
+            }
+            None => url,
+        }
+    }
+
+    fn detect_remote(&self, remote_url: String, branch: Option<String>) -> Result<GitService> {
+        let remote_url = self.rewrite_scheme(remote_url);
+        let remote_url = if self.normalize_ssh {
+            normalize_scp_like(remote_url)
+        } else {
+            remote_url
+        };
+        self.debug(&format!("Remote URL: {}", remote_url));
+        let mut remote = parse_remote_url(&remote_url)?;
+
+        if self.resolve_ssh_aliases && remote.transport() == Transport::Ssh {
+            let path = self.ssh_config_path.as_ref();
+            if let Some(host) = ssh_config::resolve_alias(path, remote.host()) {
+                self.debug(&format!(
+                    "Resolved SSH host alias '{}' to '{}'",
+                    remote.host(),
+                    host
+                ));
+                remote.set_host(&host);
+            }
+        }
+
+        let (kind, confidence) = match self.mapped_kind(remote.host()) {
+            Some(kind) => {
+                self.debug(&format!("Host '{}' is mapped to {:?}", remote.host(), kind));
+                (kind, Confidence::Mapped)
+            }
+            None => match classify_remote(&remote) {
+                Ok(classified) => classified,
+                Err(err) => {
+                    let custom = self
+                        .custom_classifier
+                        .as_ref()
+                        .and_then(|CustomClassifier(f)| f(&remote));
+                    return match custom {
+                        Some(service) => {
+                            self.debug(&format!("Custom classifier detected: {:?}", service));
+                            self.check_allowed(service)
+                        }
+                        None => Err(err),
+                    };
+                }
+            },
+        };
+        let base_path = self
+            .base_paths
+            .iter()
+            .rev()
+            .find(|(h, _)| h == remote.host())
+            .map(|(_, p)| p.as_str());
+        let rules = PathRules {
+            strip_git_suffix: self.strip_git_suffix,
+            base_path,
+            default_branch: self.default_branch.as_deref(),
+        };
+        let service = detect_with_kind(&remote, kind, confidence, branch, &rules)?;
+
+        self.debug(&format!("Detected service: {:?}", service));
+        self.check_allowed(service)
+    }
+
+    // Kind of service mapped to the normalized host name with `map_host`. The last mapping wins
+    fn mapped_kind(&self, host: &str) -> Option<ServiceKind> {
+        self.host_mappings
+            .iter()
+            .rev()
+            .find(|(h, _)| h == host)
+            .map(|(_, k)| *k)
+    }
+
+    fn check_allowed(&self, service: GitService) -> Result<GitService> {
+        if let Some(allowed) = &self.allowed_services {
+            let kind = service.kind();
+            if !allowed.contains(&kind) {
+                return Err(Error::CannotDetect {
+                    reason: format!("Detected service {:?} is not allowed", kind),
+                });
+            }
+        }
+        Ok(service)
+    }
+
+    /// Detect Git hosting service from a file path with the options. Path can
+    /// be both file path and directory path.
+    pub fn detect<P: AsRef<Path>>(&self, path: P) -> Result<GitService> {
+        let path = path.as_ref();
+        if let Some(url) = self.url_in_place_of_path(path) {
+            return self.detect_remote(url, None);
+        }
+        let git = self.git(path);
+        let (remote_url, branch) = match &self.remote_precedence {
+            Some(names) => match git.preferred_remote(names) {
+                Err(err) if self.git_not_found(&err) => {
+                    Repository::find(path)?.preferred_remote(names)?
+                }
+                result => result?,
+            },
+            None => match git.tracking_remote() {
+                Err(err) if self.git_not_found(&err) => {
+                    Repository::find(path)?.tracking_remote()?
+                }
+                result => result?,
+            },
+        };
+        self.detect_remote(remote_url, branch)
+    }
+
+    /// Try detecting Git hosting service from each path in order and return
+    /// the first successful result. When detection fails for all paths, the
+    /// error contains the errors of all paths.
+    pub fn detect_first<P: AsRef<Path>>(&self, paths: &[P]) -> Result<GitService> {
+        let mut errors = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.as_ref();
+            match self.detect(path) {
+                Ok(service) => return Ok(service),
+                Err(err) => {
+                    self.debug(&format!("Detection failed for {}: {}", path.display(), err));
+                    errors.push(format!("{}: {}", path.display(), err));
+                }
+            }
+        }
+        Err(Error::CannotDetect {
+            reason: format!("No service detected from any path: [{}]", errors.join(", ")),
+        })
+    }
+
+    /// Almost the same as `detect`, but always detect the service from `origin`
+    /// remote without looking up the upstream of the current branch.
+    pub fn detect_origin<P: AsRef<Path>>(&self, path: P) -> Result<GitService> {
+        let path = path.as_ref();
+        if let Some(url) = self.url_in_place_of_path(path) {
+            return self.detect_remote(url, None);
+        }
+        let git = self.git(path);
+        // Note: `git config --get` silently fails outside a repository
+        let branch = match git.current_branch() {
+            Ok(branch) => Some(branch),
+            Err(err) if self.git_not_found(&err) => {
+                let repo = Repository::find(path)?;
+                let remote_url = repo.remote_url("origin")?;
+                return self.detect_remote(remote_url, repo.current_branch()?);
+            }
+            Err(err @ Error::NotAGitRepository { .. }) => return Err(err),
+            Err(_) => None,
+        };
+        let remote_url = git.remote_url("origin")?;
+        self.detect_remote(remote_url, branch)
+    }
+
+    /// Path of the file relative to the root directory of its repository. It
+    /// is useful for building URLs with `GitService::blob_url` from a file path.
+    pub fn relative_path<P: AsRef<Path>>(&self, file: P) -> Result<PathBuf> {
+        let file = file.as_ref();
+        self.git(file).relative_path(file)
+    }
+
+    /// URL of the page of the file on the current branch like
+    /// `GitService::blob_url`. Unlike it, `file` is a path on the file system
+    /// which can be absolute. The service is detected from the repository of
+    /// the file and the path relative to its root is computed. It returns
+    /// `None` when neither the current branch nor the default branch is known.
+    pub fn blob_url_for_file<P: AsRef<Path>>(
+        &self,
+        file: P,
+        line: Option<u32>,
+    ) -> Result<Option<String>> {
+        let file = file.as_ref();
+        let service = self.detect(file)?;
+        let rel = self.relative_path(file)?;
+        let rel = rel.to_str().ok_or_else(|| Error::CannotDetect {
+            reason: format!("Path {} is not valid UTF-8", rel.display()),
+        })?;
+        Ok(service.blob_url(rel, line))
+    }
+
+    /// Default branch of the remote like `main` read from local
+    /// `refs/remotes/{remote}/HEAD`. Network is never accessed. It returns
+    /// `None` when the ref is not set locally. `git remote set-head {remote}
+    /// --auto` sets it.
+    pub fn remote_default_branch<P: AsRef<Path>>(
+        &self,
+        path: P,
+        remote: &str,
+    ) -> Result<Option<String>> {
+        self.git(path.as_ref()).remote_default_branch_local(remote)
+    }
+
+    /// Whether `branch` exists on the remote according to local
+    /// `refs/remotes/{remote}/{branch}`. Network is never accessed, so the
+    /// result reflects the last fetch. It is useful for checking a link to
+    /// create a pull request will work.
+    pub fn branch_exists_on_remote<P: AsRef<Path>>(
+        &self,
+        path: P,
+        remote: &str,
+        branch: &str,
+    ) -> Result<bool> {
+        self.git(path.as_ref())
+            .branch_exists_on_remote(remote, branch)
+    }
+
+    /// Nearest tag reachable from HEAD with the number of commits after the tag
+    /// and abbreviated SHA like `v1.2.3-4-gabcdef` (`git describe --tags --always`).
+    /// When no tag is reachable, abbreviated SHA of HEAD is returned.
+    pub fn describe<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        self.git(path.as_ref()).describe()
+    }
+
+    /// Whether the repository is a shallow clone. History of a shallow clone
+    /// is limited so commits which permalinks point to may be unavailable.
+    pub fn is_shallow<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.git(path.as_ref()).is_shallow()
+    }
+
+    /// Local branches of the repository with their upstreams.
+    pub fn branches<P: AsRef<Path>>(&self, path: P) -> Result<Vec<BranchInfo>> {
+        self.git(path.as_ref()).branches()
+    }
+
+    /// Detect Git hosting services from all remotes of the repository. It
+    /// returns pairs of remote name and detected service. Remotes whose
+    /// service cannot be detected are skipped. Branch is always `None` since
+    /// remotes are not related to the current branch. URLs are read from Git
+    /// config at once and `url.<base>.insteadOf` rewrites are applied to them.
+    pub fn detect_all_remotes<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(String, GitService)>> {
+        let remotes = self.git(path.as_ref()).all_remote_urls()?;
+        Ok(self.detect_remotes(remotes))
+    }
+
+    // Detect services from pairs of remote name and URL. Remotes whose service cannot be detected
+    // are skipped
+    fn detect_remotes(&self, remotes: Vec<(String, String)>) -> Vec<(String, GitService)> {
+        let mut services = Vec::with_capacity(remotes.len());
+        for (name, url) in remotes {
+            match self.detect_remote(url, None) {
+                Ok(service) => services.push((name, service)),
+                Err(err) => self.debug(&format!("Skipped remote '{}': {}", name, err)),
+            }
+        }
+        services
+    }
+
+    /// Detect Git hosting service from a Go module path with the options. Host
+    /// of vanity import path like `go.example.com/foo` can be mapped to a
+    /// service with `map_host`. See `detect_from_go_module` for more details.
+    pub fn detect_go_module(&self, module: &str) -> Result<GitService> {
+        self.detect_remote(go_module_url(module), None)
+    }
+
+    /// Detect GitHub repository from shorthand `user/repo` on the host set by
+    /// `github_host`. See `detect_github_shorthand` for more details.
+    pub fn detect_github_shorthand(&self, shorthand: &str) -> Result<GitService> {
+        let url = github_shorthand_url(shorthand, &self.github_host)?;
+        self.detect_remote(url, None)
+    }
+
+    /// Classify a host name into a kind of Git hosting service with the
+    /// options. Mappings added by `map_host` are consulted before the built-in
+    /// rules of `classify_host`. Custom classifier is not consulted since it
+    /// requires a whole remote URL.
+    pub fn classify_host(&self, host: &str) -> Option<ServiceKind> {
+        self.mapped_kind(&normalize_host(host))
+            .or_else(|| classify_host(host))
+    }
+
+    /// Find all Git repositories under `root` and detect the service of each.
+    /// Repositories nested in other repositories are found only when
+    /// `scan_nested_repositories` is enabled. Depth of the scan can be limited
+    /// with `max_scan_depth`. It returns pairs of the root directory of
+    /// repository and the result of detection sorted by path.
+    pub fn detect_workspace<P: AsRef<Path>>(&self, root: P) -> Vec<(PathBuf, Result<GitService>)> {
+        let mut repos = vec![];
+        find_repositories(
+            root.as_ref(),
+            self.scan_nested_repositories,
+            self.max_scan_depth,
+            &mut repos,
+        );
+        repos
+            .into_iter()
+            .map(|repo| {
+                let result = self.detect(&repo);
+                (repo, result)
+            })
+            .collect()
+    }
+
+    /// Detect Git hosting services of submodules declared in `.gitmodules` at
+    /// the root of the repository. It returns pairs of submodule name and
+    /// detected service. Relative URL like `../foo.git` is resolved against
+    /// the remote URL of the repository. Submodules whose service cannot be
+    /// detected are skipped.
+    pub fn detect_submodules<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(String, GitService)>> {
+        let git = self.git(path.as_ref());
+        let config = Config::read(&git.toplevel()?.join(".gitmodules"))?;
+        let mut base_url = None;
+        let mut services = vec![];
+        for (key, url) in config.entries() {
+            let name = match key
+                .strip_prefix("submodule.")
+                .and_then(|k| k.strip_suffix(".url"))
+            {
+                Some(name) => name,
+                None => continue,
+            };
+            let url = if url.starts_with("./") || url.starts_with("../") {
+                if base_url.is_none() {
+                    base_url = Some(git.tracking_remote()?.0);
+                }
+                resolve_relative_url(base_url.as_deref().unwrap(), url)
+            } else {
+                url.to_string()
+            };
+            match self.detect_remote(url, None) {
+                Ok(service) => services.push((name.to_string(), service)),
+                Err(err) => self.debug(&format!("Skipped submodule '{}': {}", name, err)),
+            }
+        }
+        Ok(services)
+    }
+
+    /// Detect the service of the remote tracked by the current branch as
+    /// primary and services of other remotes as its mirrors. When the current
+    /// branch has no upstream, the primary is chosen by `remote_precedence` in
+    /// the same way as `detect`. Mirrors are pairs of remote name and detected
+    /// service. Remotes pointing to the same repository as primary and remotes
+    /// whose service cannot be detected are not included in mirrors.
+    pub fn primary_and_mirrors<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(GitService, Vec<(String, GitService)>)> {
+        let path = path.as_ref();
+        let git = self.git(path);
+        let ((remote_url, branch), remotes) = match git.upstream() {
+            Err(err) if self.git_not_found(&err) => {
+                let repo = Repository::find(path)?;
+                let primary = match (repo.upstream()?, &self.remote_precedence) {
+                    (Some((name, branch)), _) => (repo.remote_url(&name)?, Some(branch)),
+                    (None, Some(names)) => repo.preferred_remote(names)?,
+                    (None, None) => repo.tracking_remote()?,
+                };
+                (primary, repo.all_remote_urls()?)
+            }
+            upstream => {
+                let primary = match (upstream?, &self.remote_precedence) {
+                    (Some((name, branch)), _) => (git.remote_url(name)?, Some(branch)),
+                    (None, Some(names)) => git.preferred_remote(names)?,
+                    (None, None) => git.tracking_remote()?,
+                };
+                (primary, git.all_remote_urls()?)
+            }
+        };
+        let primary = self.detect_remote(remote_url, branch)?;
+        let mirrors = self
+            .detect_remotes(remotes)
+            .into_iter()
+            .filter(|(_, s)| !s.same_repo(&primary))
+            .collect();
+        Ok((primary, mirrors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{commit, git_repo, run_git, set_upstream, temp_dir};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn normalize_ssh_on_and_off() {
+        use std::sync::Mutex;
+
+        let dir = git_repo(
+            "options-normalize-ssh",
+            &[("origin", "git@github.com:rhysd/detect_git_service.git")],
+        );
+        for (normalize, url) in &[
+            (true, "ssh://git@github.com/rhysd/detect_git_service.git"),
+            (false, "git@github.com:rhysd/detect_git_service.git"),
+        ] {
+            let messages = Arc::new(Mutex::new(vec![]));
+            let captured = messages.clone();
+            let service = DetectOptions::new()
+                .normalize_ssh(*normalize)
+                .on_debug(move |msg| captured.lock().unwrap().push(msg.to_string()))
+                .detect(&dir)
+                .unwrap();
+            let remote_url = format!("Remote URL: {}", url);
+            let messages = messages.lock().unwrap();
+            assert!(messages.contains(&remote_url), "{:?}", messages);
+
+            let parsed = Arc::new(Mutex::new(None));
+            let captured = parsed.clone();
+            let err = DetectOptions::new()
+                .normalize_ssh(*normalize)
+                .custom_classifier(move |remote| {
+                    *captured.lock().unwrap() = Some(remote.url().to_string());
+                    None
+                })
+                .detect_remote(
+                    "git@unknown.example.com:rhysd/detect_git_service.git".to_string(),
+                    None,
+                )
+                .unwrap_err();
+            assert!(matches!(err, Error::CannotDetect { .. }), "{:?}", err);
+            let expected = url.replace("github.com", "unknown.example.com");
+            assert_eq!(parsed.lock().unwrap().as_deref(), Some(expected.as_str()));
+
+            match service {
+                GitService::GitHub {
+                    ref user,
+                    ref repo,
+                    ref transport,
+                    ..
+                } => {
+                    assert_eq!(user, "rhysd");
+                    assert_eq!(repo, "detect_git_service");
+                    assert_eq!(transport, &Transport::Ssh);
+                }
+                _ => panic!("unexpected service: {:?}", service),
+            }
+        }
+    }
+
+    #[test]
+    fn explicit_git_dir() {
+        let work_tree = temp_dir("options-explicit-git-dir");
+        let git_dir = env::current_dir().unwrap().join(".git");
+        let opts = DetectOptions::new().git_dir(&git_dir);
+        for service in &[
+            opts.detect(&work_tree).unwrap(),
+            opts.detect_origin(&work_tree).unwrap(),
+        ] {
+            assert_eq!(service.kind(), ServiceKind::GitHub);
+            assert_eq!(service.user(), "rhysd");
+            assert_eq!(service.repo(), "detect_git_service");
+        }
+    }
+
+    #[test]
+    fn strip_git_suffix_on_and_off() {
+        let dir = git_repo(
+            "options-strip-git-suffix",
+            &[("origin", "https://github.com/rhysd/something.git")],
+        );
+        for (strip, expected) in &[(true, "something"), (false, "something.git")] {
+            let service = DetectOptions::new()
+                .strip_git_suffix(*strip)
+                .detect(&dir)
+                .unwrap();
+            assert_eq!(service.repo(), expected);
+        }
+    }
+
+    #[test]
+    fn url_in_place_of_path() {
+        let url = "https://gitlab.com/Linda_pp/detect_git_service.git";
+        // Without the option, the URL is treated as a nonexistent path in the current directory
+        let service = DetectOptions::new().detect(url).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+
+        let opts = DetectOptions::new().accept_url(true);
+        for service in &[opts.detect(url).unwrap(), opts.detect_origin(url).unwrap()] {
+            assert_eq!(service.kind(), ServiceKind::GitLab);
+            assert_eq!(service.user(), "Linda_pp");
+            assert_eq!(service.branch(), &None);
+        }
+
+        // Normal paths are not affected
+        let service = opts.detect(".").unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+    }
+
+    #[test]
+    fn first_successful_path() {
+        let not_repo = temp_dir("options-detect-first-not-repo");
+        let repo = git_repo(
+            "options-detect-first-repo",
+            &[(
+                "origin",
+                "https://gitlab.com/Linda_pp/detect_git_service.git",
+            )],
+        );
+        let opts = DetectOptions::new();
+        let service = opts.detect_first(&[&not_repo, &repo]).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitLab);
+
+        let err = opts.detect_first(&[&not_repo, &not_repo]).unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("No service detected from any path"), "{}", msg);
+        assert_eq!(msg.matches("Not a Git repository").count(), 2, "{}", msg);
+
+        let paths: &[&Path] = &[];
+        assert!(opts.detect_first(paths).is_err());
+    }
+
+    #[test]
+    fn schemeless_url_in_place_of_path() {
+        let url = "gitlab.com/Linda_pp/detect_git_service";
+        let service = DetectOptions::new().detect(url).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+
+        let opts = DetectOptions::new().accept_schemeless_url(true);
+        let service = opts.detect(url).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitLab);
+        assert_eq!(service.user(), "Linda_pp");
+        assert_eq!(service.repo(), "detect_git_service");
+
+        // Existing path is not treated as URL even if it looks like URL
+        let dir = temp_dir("options-schemeless-url")
+            .join("gitlab.com")
+            .join("foo");
+        fs::create_dir_all(&dir).unwrap();
+        let err = opts.detect(&dir).unwrap_err();
+        match err {
+            Error::NotAGitRepository { .. } => {}
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn url_instead_of() {
+        let dir = git_repo(
+            "options-url-instead-of",
+            &[("origin", "gh:rhysd/detect_git_service")],
+        );
+        run_git(
+            &dir,
+            &["config", "url.https://github.com/.insteadOf", "gh:"],
+        );
+        let service = DetectOptions::new().detect_origin(&dir).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.repo(), "detect_git_service");
+    }
+
+    #[test]
+    fn fallback_to_config() {
+        let dir = git_repo(
+            "options-fallback-to-config",
+            &[(
+                "origin",
+                "https://gitlab.com/Linda_pp/detect_git_service.git",
+            )],
+        );
+        let opts = DetectOptions::new().git_command("/path/to/not/existing/git");
+        match opts.detect(&dir).unwrap_err() {
+            Error::CommandCannotRun(_) => {}
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let opts = opts.fallback_to_config(true);
+        for service in &[
+            opts.detect(&dir).unwrap(),
+            opts.detect_origin(&dir).unwrap(),
+        ] {
+            assert_eq!(service.kind(), ServiceKind::GitLab);
+            assert_eq!(service.user(), "Linda_pp");
+            assert_eq!(service.branch().as_ref().unwrap(), "main");
+        }
+    }
+
+    #[test]
+    fn service_not_allowed() {
+        let err = DetectOptions::new()
+            .allowed_services(&[ServiceKind::GitLab, ServiceKind::GitHubEnterprise])
+            .detect(".")
+            .unwrap_err();
+        match err {
+            Error::CannotDetect { ref reason } => {
+                assert_eq!(reason, "Detected service GitHub is not allowed")
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn service_allowed() {
+        let service = DetectOptions::new()
+            .allowed_services(&[ServiceKind::GitHub])
+            .detect(".")
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+    }
+
+    #[test]
+    fn origin_or_upstream() {
+        let dir = git_repo(
+            "options-detect-origin",
+            &[
+                ("origin", "https://github.com/rhysd/detect_git_service.git"),
+                (
+                    "upstream",
+                    "https://gitlab.com/Linda_pp/detect_git_service.git",
+                ),
+            ],
+        );
+        commit(&dir);
+        set_upstream(&dir, "upstream", "dev");
+
+        let opts = DetectOptions::new();
+        let service = opts.detect(&dir).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitLab);
+        assert_eq!(service.branch().as_ref().unwrap(), "dev");
+
+        let service = opts.detect_origin(&dir).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.branch().as_ref().unwrap(), "main");
+    }
+
+    #[test]
+    fn all_remotes() {
+        let dir = git_repo(
+            "options-all-remotes",
+            &[
+                ("origin", "https://github.com/rhysd/detect_git_service.git"),
+                ("local", "/path/to/detect_git_service"),
+                ("upstream", "git@gitlab.com:Linda_pp/detect_git_service.git"),
+                ("mirror", "bb:rhysd/detect_git_service.git"),
+            ],
+        );
+        run_git(
+            &dir,
+            &["config", "url.https://bitbucket.org/.insteadOf", "bb:"],
+        );
+        let services = DetectOptions::new().detect_all_remotes(&dir).unwrap();
+        let found: Vec<_> = services
+            .iter()
+            .map(|(name, s)| (name.as_str(), s.kind()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("origin", ServiceKind::GitHub),
+                ("upstream", ServiceKind::GitLab),
+                ("mirror", ServiceKind::Bitbucket),
+            ],
+        );
+    }
+
+    #[test]
+    fn primary_and_mirrors() {
+        let dir = git_repo(
+            "options-primary-and-mirrors",
+            &[
+                ("origin", "https://github.com/rhysd/detect_git_service.git"),
+                ("origin-ssh", "git@github.com:rhysd/detect_git_service.git"),
+                (
+                    "mirror",
+                    "https://gitlab.com/Linda_pp/detect_git_service.git",
+                ),
+            ],
+        );
+        commit(&dir);
+        let (primary, mirrors) = DetectOptions::new().primary_and_mirrors(&dir).unwrap();
+        assert_eq!(primary.kind(), ServiceKind::GitHub);
+        assert_eq!(primary.branch().as_ref().unwrap(), "main");
+        assert_eq!(mirrors.len(), 1, "{:?}", mirrors);
+        assert_eq!(mirrors[0].0, "mirror");
+        assert_eq!(mirrors[0].1.kind(), ServiceKind::GitLab);
+
+        // Without upstream, primary is chosen by remote precedence like `detect`
+        let opts = DetectOptions::new().remote_precedence(["mirror", "origin"]);
+        let (primary, mirrors) = opts.primary_and_mirrors(&dir).unwrap();
+        assert_eq!(primary.kind(), ServiceKind::GitLab);
+        let names: Vec<_> = mirrors.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["origin", "origin-ssh"]);
+
+        // Upstream of the current branch takes precedence
+        set_upstream(&dir, "origin", "main");
+        let (primary, _) = opts.primary_and_mirrors(&dir).unwrap();
+        assert_eq!(primary.kind(), ServiceKind::GitHub);
+
+        let opts = opts
+            .git_command("/path/to/not/existing/git")
+            .fallback_to_config(true);
+        let (primary, mirrors) = opts.primary_and_mirrors(&dir).unwrap();
+        assert_eq!(primary.kind(), ServiceKind::GitHub);
+        assert_eq!(primary.branch().as_ref().unwrap(), "main");
+        let names: Vec<_> = mirrors.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["mirror"]);
+    }
+
+    #[test]
+    fn ssh_host_alias() {
+        let dir = git_repo(
+            "options-ssh-host-alias",
+            &[("origin", "git@myalias:rhysd/detect_git_service.git")],
+        );
+        let config = temp_dir("options-ssh-host-alias-config").join("config");
+        fs::write(
+            &config,
+            "Host myalias\n    HostName github.com\n    User git\n",
+        )
+        .unwrap();
+
+        let err = DetectOptions::new().detect(&dir).unwrap_err();
+        assert!(
+            format!("{}", err).contains("No service detected"),
+            "{}",
+            err
+        );
+
+        let service = DetectOptions::new()
+            .resolve_ssh_aliases(true)
+            .ssh_config_path(&config)
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.host(), "github.com");
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "detect_git_service");
+    }
+
+    #[test]
+    fn host_mapping() {
+        let dir = git_repo(
+            "options-host-mapping",
+            &[(
+                "origin",
+                "https://code.example.com/rhysd/detect_git_service.git",
+            )],
+        );
+
+        let err = DetectOptions::new().detect(&dir).unwrap_err();
+        assert!(
+            format!("{}", err).contains("No service detected"),
+            "{}",
+            err
+        );
+
+        let service = DetectOptions::new()
+            .map_host("Code.Example.com", ServiceKind::GitLab)
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitLab);
+        assert_eq!(service.host(), "code.example.com");
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.confidence(), &Confidence::Mapped);
+    }
+
+    #[test]
+    fn default_branch_for_urls() {
+        let dir = git_repo(
+            "options-default-branch",
+            &[("origin", "https://github.com/rhysd/detect_git_service.git")],
+        );
+        let service = DetectOptions::new().detect(&dir).unwrap();
+        assert_eq!(service.branch(), &None);
+        assert_eq!(service.blob_url("README.md", None), None);
+
+        let service = DetectOptions::new()
+            .default_branch("main")
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.branch(), &None);
+        assert_eq!(
+            service.blob_url("README.md", Some(1)).unwrap(),
+            "https://github.com/rhysd/detect_git_service/blob/main/README.md#L1"
+        );
+        assert_eq!(
+            service.edit_url("README.md").unwrap(),
+            "https://github.com/rhysd/detect_git_service/edit/main/README.md"
+        );
+    }
+
+    #[test]
+    fn workspace_with_nested_repositories() {
+        let root = temp_dir("options-workspace");
+        let outer = root.join("outer");
+        let inner = outer.join("vendor").join("inner");
+        let not_repo = root.join("docs");
+        for (dir, url) in &[
+            (&outer, "https://github.com/rhysd/outer.git"),
+            (&inner, "https://gitlab.com/Linda_pp/inner.git"),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+            run_git(dir, &["init", "-q"]);
+            run_git(dir, &["remote", "add", "origin", url]);
+        }
+        fs::create_dir_all(&not_repo).unwrap();
+
+        let repos: Vec<_> = DetectOptions::new()
+            .detect_workspace(&root)
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect();
+        assert_eq!(repos, vec![outer.clone()]);
+
+        let repos: Vec<_> = DetectOptions::new()
+            .scan_nested_repositories(true)
+            .max_scan_depth(2)
+            .detect_workspace(&root)
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect();
+        assert_eq!(repos, vec![outer.clone()]);
+
+        let results = DetectOptions::new()
+            .scan_nested_repositories(true)
+            .detect_workspace(&root);
+        let repos: Vec<_> = results.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(repos, vec![outer, inner]);
+        let services: Vec<_> = results
+            .into_iter()
+            .map(|(_, r)| {
+                let s = r.unwrap();
+                (s.kind(), s.repo().to_string())
+            })
+            .collect();
+        assert_eq!(
+            services,
+            vec![
+                (ServiceKind::GitHub, "outer".to_string()),
+                (ServiceKind::GitLab, "inner".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_for_git_command() {
+        let dir = git_repo(
+            "options-env",
+            &[("origin", "gh:rhysd/detect_git_service.git")],
+        );
+        let home = temp_dir("options-env-home");
+        fs::write(
+            home.join(".gitconfig"),
+            "[url \"https://github.com/\"]\n\tinsteadOf = gh:\n",
+        )
+        .unwrap();
+        let service = DetectOptions::new()
+            .env("HOME", home.to_str().unwrap())
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.repo(), "detect_git_service");
+    }
+
+    #[test]
+    fn submodules() {
+        let dir = git_repo(
+            "options-submodules",
+            &[("origin", "git@github.com:rhysd/detect_git_service.git")],
+        );
+        commit(&dir);
+        fs::write(
+            dir.join(".gitmodules"),
+            r#"[submodule "absolute"]
+	path = vendor/absolute
+	url = https://gitlab.com/Linda_pp/absolute.git
+[submodule "relative"]
+	path = vendor/relative
+	url = ../relative.git
+[submodule "unknown"]
+	path = vendor/unknown
+	url = https://example.com/foo/unknown.git
+"#,
+        )
+        .unwrap();
+
+        let subs = DetectOptions::new().detect_submodules(&dir).unwrap();
+        let subs: Vec<_> = subs
+            .iter()
+            .map(|(n, s)| (n.as_str(), s.kind(), s.user().as_str(), s.repo().as_str()))
+            .collect();
+        assert_eq!(
+            subs,
+            vec![
+                ("absolute", ServiceKind::GitLab, "Linda_pp", "absolute"),
+                ("relative", ServiceKind::GitHub, "rhysd", "relative"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blob_url_for_absolute_file_path() {
+        let dir = git_repo(
+            "options-blob-url-for-file",
+            &[("origin", "https://github.com/rhysd/detect_git_service.git")],
+        );
+        commit(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src").join("lib.rs"), "").unwrap();
+        let url = DetectOptions::new()
+            .blob_url_for_file(dir.join("src").join("lib.rs"), Some(3))
+            .unwrap();
+        assert_eq!(
+            url.unwrap(),
+            "https://github.com/rhysd/detect_git_service/blob/main/src/lib.rs#L3"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn blob_url_of_windows_path() {
+        let opts = DetectOptions::new();
+        let rel = opts.relative_path(r"src\service.rs").unwrap();
+        assert_eq!(rel, Path::new(r"src\service.rs"));
+        let service = opts.detect(".").unwrap();
+        let url = service.blob_url(rel.to_str().unwrap(), None).unwrap();
+        assert!(url.ends_with("/src/service.rs"), "{}", url);
+    }
+
+    #[test]
+    fn shallow_clone() {
+        let opts = DetectOptions::new();
+        let dir = git_repo("options-shallow-clone", &[]);
+        commit(&dir);
+        assert!(!opts.is_shallow(&dir).unwrap());
+
+        let clone = temp_dir("options-shallow-clone-dst");
+        let src = format!("file://{}", dir.display());
+        run_git(&clone, &["clone", "--depth=1", "-q", &src, "repo"]);
+        assert!(opts.is_shallow(clone.join("repo")).unwrap());
+    }
+
+    #[test]
+    fn bitbucket_server_on_mapped_host() {
+        let dir = git_repo(
+            "options-bitbucket-server",
+            &[(
+                "origin",
+                "https://git.example/scm/PROJ/detect_git_service.git",
+            )],
+        );
+        let service = DetectOptions::new()
+            .map_host("git.example", ServiceKind::BitbucketServer)
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::BitbucketServer);
+        assert_eq!(service.confidence(), &Confidence::Mapped);
+        assert_eq!(service.user(), "PROJ");
+        assert_eq!(service.repo(), "detect_git_service");
+        assert_eq!(
+            service.web_url(),
+            "https://git.example/projects/PROJ/repos/detect_git_service"
+        );
+    }
+
+    #[test]
+    fn go_module_on_vanity_host() {
+        let service = DetectOptions::new()
+            .map_host("go.example.com", ServiceKind::GitHub)
+            .detect_go_module("go.example.com/rhysd/foo/v2")
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.confidence(), &Confidence::Mapped);
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "foo");
+    }
+
+    #[test]
+    fn github_shorthand_on_enterprise() {
+        let service = DetectOptions::new()
+            .github_host("GitHub.MyCompany.com")
+            .detect_github_shorthand("rhysd/detect_git_service")
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHubEnterprise);
+        assert_eq!(service.host(), "github.mycompany.com");
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "detect_git_service");
+
+        let err = DetectOptions::new()
+            .detect_github_shorthand("rhysd/detect_git_service/tree")
+            .unwrap_err();
+        assert!(format!("{}", err).contains("Invalid shorthand"), "{}", err);
+    }
+
+    #[test]
+    fn rewrite_custom_scheme() {
+        let dir = git_repo(
+            "options-map-scheme",
+            &[("origin", "sso://github.com/rhysd/detect_git_service")],
+        );
+        assert!(DetectOptions::new().detect(&dir).is_err());
+
+        let service = DetectOptions::new()
+            .map_scheme("SSO", "https")
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+        assert_eq!(service.transport(), &Transport::Https);
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "detect_git_service");
+    }
+
+    #[test]
+    fn classify_host_with_mappings() {
+        let opts = DetectOptions::new()
+            .map_host("code.example.com", ServiceKind::Gitea)
+            .map_host("GitHub.Mycompany.com", ServiceKind::GitLab);
+        assert_eq!(
+            opts.classify_host("code.example.com"),
+            Some(ServiceKind::Gitea)
+        );
+        assert_eq!(
+            opts.classify_host("github.mycompany.com"),
+            Some(ServiceKind::GitLab)
+        );
+        assert_eq!(opts.classify_host("github.com"), Some(ServiceKind::GitHub));
+        assert_eq!(opts.classify_host("unknown.example.com"), None);
+    }
+
+    #[test]
+    fn custom_classifier_for_unknown_host() {
+        let classifier = |remote: &ParsedRemote| {
+            if remote.host() != "code.example.org" {
+                return None;
+            }
+            // Repositories are served at '/git/{user}/{repo}' on this host
+            match remote.segments().as_slice() {
+                ["git", user, repo] => Some(GitService::Gitea {
+                    host: remote.host().to_string(),
+                    user: user.to_string(),
+                    repo: repo.to_string(),
+                    branch: None,
+                    transport: remote.transport(),
+                    confidence: Confidence::Mapped,
+                    path_segments: vec![user.to_string(), repo.to_string()],
+                    base_path: Some("/git".to_string()),
+                    default_branch: None,
+                    port: remote.port(),
+                }),
+                _ => None,
+            }
+        };
+        let opts = DetectOptions::new().custom_classifier(classifier);
+
+        let service = opts
+            .detect_go_module("code.example.org/git/rhysd/tool")
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::Gitea);
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "tool");
+        assert_eq!(service.web_url(), "https://code.example.org/git/rhysd/tool");
+
+        // Built-in rules take precedence over the custom classifier
+        let service = opts.detect_go_module("github.com/rhysd/tool").unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+
+        assert!(opts
+            .detect_go_module("unknown.example.org/rhysd/tool")
+            .is_err());
+        let opts = opts.allowed_services(&[ServiceKind::GitHub]);
+        assert!(opts
+            .detect_go_module("code.example.org/git/rhysd/tool")
+            .is_err());
+    }
+
+    #[test]
+    fn remote_precedence() {
+        let dir = git_repo(
+            "options-remote-precedence",
+            &[
+                ("origin", "https://github.com/rhysd/detect_git_service.git"),
+                (
+                    "upstream",
+                    "https://gitlab.com/Linda_pp/detect_git_service.git",
+                ),
+            ],
+        );
+        commit(&dir);
+        set_upstream(&dir, "origin", "main");
+
+        let service = DetectOptions::new().detect(&dir).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+
+        let opts = DetectOptions::new().remote_precedence(["fork", "upstream", "origin"]);
+        let service = opts.detect(&dir).unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitLab);
+        assert_eq!(service.user(), "Linda_pp");
+        assert_eq!(service.branch().as_deref(), Some("main"));
+
+        let err = DetectOptions::new()
+            .remote_precedence(vec!["fork".to_string()])
+            .detect(&dir)
+            .unwrap_err();
+        assert!(
+            format!("{}", err).contains("None of remotes fork"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn base_path_of_host() {
+        let dir = git_repo(
+            "options-base-path",
+            &[(
+                "origin",
+                "https://git.example.com/gitea/rhysd/detect_git_service.git",
+            )],
+        );
+        let service = DetectOptions::new()
+            .map_host("git.example.com", ServiceKind::Gitea)
+            .base_path("git.example.com", "/gitea")
+            .detect(&dir)
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::Gitea);
+        assert_eq!(service.user(), "rhysd");
+        assert_eq!(service.repo(), "detect_git_service");
+        assert_eq!(
+            service.web_url(),
+            "https://git.example.com/gitea/rhysd/detect_git_service"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn git_version_probed_once() {
+        use std::sync::Mutex;
+
+        let log = temp_dir("options-git-version-probed-once-log").join("log");
+        let script = format!(
+            r#"echo "$*" >> '{}'
+case "$*" in
+    *--version*) echo 'unknown version' >&2; exit 1 ;;
+    *) echo 'v1.2.3' ;;
+esac"#,
+            log.display(),
+        );
+        let cmd = crate::testing::fake_git("options-git-version-probed-once", &script);
+        let messages = Arc::new(Mutex::new(vec![]));
+        let captured = messages.clone();
+        let opts = DetectOptions::new()
+            .git_command(cmd.to_str().unwrap())
+            .on_debug(move |msg| captured.lock().unwrap().push(msg.to_string()));
+        assert_eq!(opts.describe(".").unwrap(), "v1.2.3");
+        assert_eq!(opts.describe("src").unwrap(), "v1.2.3");
+
+        let log = fs::read_to_string(&log).unwrap();
+        let probes = log.lines().filter(|l| l.contains("--version")).count();
+        assert_eq!(probes, 1, "{}", log);
+        let messages = messages.lock().unwrap();
+        assert!(
+            messages
+                .iter()
+                .any(|m| m.starts_with("Cannot get version of git: unknown version")),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn url_of_only_base_path() {
+        let err = DetectOptions::new()
+            .accept_url(true)
+            .map_host("bb.example.com", ServiceKind::BitbucketServer)
+            .base_path("bb.example.com", "/bitbucket")
+            .detect("https://bb.example.com/bitbucket")
+            .unwrap_err();
+        match err {
+            Error::CannotDetect { ref reason } => {
+                assert!(reason.contains("is empty"), "{}", reason)
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn debug_callback() {
+        use std::sync::Mutex;
+
+        let messages = Arc::new(Mutex::new(vec![]));
+        let captured = messages.clone();
+        let service = DetectOptions::new()
+            .on_debug(move |msg| captured.lock().unwrap().push(msg.to_string()))
+            .detect(".")
+            .unwrap();
+        assert_eq!(service.kind(), ServiceKind::GitHub);
+
+        let messages = messages.lock().unwrap();
+        let has = |s: &str| messages.iter().any(|m| m.contains(s));
+        assert!(has("Running `git -C "), "{:?}", messages);
+        assert!(has("\"rev-parse\""), "{:?}", messages);
+        assert!(has("Remote URL: "), "{:?}", messages);
+        assert!(has("Detected service: GitHub"), "{:?}", messages);
+    }
+} // mod tests
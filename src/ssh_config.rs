@@ -0,0 +1,137 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Match a host name against a pattern of SSH config. '*' matches zero or more characters and '?'
+// matches exactly one character.
+fn matches_pattern(pattern: &[u8], host: &[u8]) -> bool {
+    match (pattern.first(), host.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_pattern(&pattern[1..], host)
+                || (!host.is_empty() && matches_pattern(pattern, &host[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_pattern(&pattern[1..], &host[1..]),
+        (Some(p), Some(h)) if p.eq_ignore_ascii_case(h) => {
+            matches_pattern(&pattern[1..], &host[1..])
+        }
+        _ => false,
+    }
+}
+
+// Check 'Host' patterns. A negated pattern like '!foo' cancels the match.
+fn matches_host(patterns: &[&str], host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if matches_pattern(negated.as_bytes(), host.as_bytes()) {
+                return false;
+            }
+        } else if matches_pattern(pattern.as_bytes(), host.as_bytes()) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+// Resolve 'HostName' of the host alias in the content of SSH config. As ssh does, the first
+// matching value wins.
+pub fn resolve_host_name(config: &str, alias: &str) -> Option<String> {
+    // Note: Parameters before any 'Host' line are applied to all hosts
+    let mut in_block = true;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line
+            .splitn(2, |c: char| c.is_whitespace() || c == '=')
+            .map(str::trim);
+        let keyword = words.next()?.to_ascii_lowercase();
+        let args = words.next().unwrap_or("").trim_start_matches('=').trim();
+        match keyword.as_str() {
+            "host" => {
+                let patterns: Vec<_> = args.split_whitespace().collect();
+                in_block = matches_host(&patterns, alias);
+            }
+            // Note: Conditions of 'Match' are not supported. Parameters in the block are ignored
+            "match" => in_block = false,
+            "hostname" if in_block => {
+                let name = args.trim_matches('"').replace("%h", alias);
+                return Some(name);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".ssh").join("config"))
+}
+
+// Resolve the alias with SSH config file. Missing or unreadable file is treated as no alias.
+pub fn resolve_alias(config_path: Option<&PathBuf>, alias: &str) -> Option<String> {
+    let path = match config_path {
+        Some(p) => p.clone(),
+        None => default_config_path()?,
+    };
+    let content = fs::read_to_string(path).ok()?;
+    resolve_host_name(&content, alias)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+# Comment
+Host *.example.com !secret.example.com
+    HostName gitlab.com
+
+Host myalias other
+    HostName github.com
+    User git
+
+Host  first
+    HostName=github.com
+    HostName gitlab.com
+
+Match host foo
+    HostName bitbucket.org
+";
+
+    #[test]
+    fn resolve_alias_host_name() {
+        assert_eq!(
+            resolve_host_name(CONFIG, "myalias").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            resolve_host_name(CONFIG, "other").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            resolve_host_name(CONFIG, "first").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            resolve_host_name(CONFIG, "foo.example.com").as_deref(),
+            Some("gitlab.com")
+        );
+        assert_eq!(resolve_host_name(CONFIG, "secret.example.com"), None);
+        assert_eq!(resolve_host_name(CONFIG, "foo"), None);
+        assert_eq!(resolve_host_name(CONFIG, "github.com"), None);
+    }
+
+    #[test]
+    fn match_wildcard_pattern() {
+        assert!(matches_pattern(b"*", b"github.com"));
+        assert!(matches_pattern(b"git*.com", b"github.com"));
+        assert!(matches_pattern(b"g?thub.com", b"github.com"));
+        assert!(matches_pattern(b"GitHub.com", b"github.com"));
+        assert!(!matches_pattern(b"g?thub.com", b"gthub.com"));
+        assert!(!matches_pattern(b"*.org", b"github.com"));
+    }
+} // mod tests
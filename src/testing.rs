@@ -0,0 +1,94 @@
+// Helpers shared by unit tests
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Create an empty directory dedicated to the test under the system temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "detect_git_service-{}-{}",
+        name,
+        std::process::id()
+    ));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Create a fake `git` executable which runs the given shell script.
+#[cfg(unix)]
+pub fn fake_git(name: &str, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_dir(name).join("git");
+    fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+pub fn run_git(dir: &Path, args: &[&str]) {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+// Create a new Git repository with the given remotes (pairs of name and URL).
+pub fn git_repo(name: &str, remotes: &[(&str, &str)]) -> PathBuf {
+    let dir = temp_dir(name);
+    run_git(&dir, &["init", "-q"]);
+    run_git(&dir, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+    for (name, url) in remotes {
+        run_git(&dir, &["remote", "add", name, url]);
+    }
+    dir
+}
+
+// Create an empty commit on the current branch.
+pub fn commit(dir: &Path) {
+    run_git(
+        dir,
+        &[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--allow-empty",
+            "-q",
+            "-m",
+            "test",
+        ],
+    );
+}
+
+// Make the current branch 'main' track the branch of the remote.
+pub fn set_upstream(dir: &Path, remote: &str, branch: &str) {
+    run_git(
+        dir,
+        &[
+            "update-ref",
+            &format!("refs/remotes/{}/{}", remote, branch),
+            "HEAD",
+        ],
+    );
+    run_git(dir, &["config", "branch.main.remote", remote]);
+    run_git(
+        dir,
+        &[
+            "config",
+            "branch.main.merge",
+            &format!("refs/heads/{}", branch),
+        ],
+    );
+}
@@ -0,0 +1,177 @@
+#![cfg(not(feature = "no_process"))]
+
+use detect_git_service::{detect, detect_all_remotes, Error, GitService};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn assert_this_repo(path: &Path) {
+    let service = detect(path).unwrap();
+    match service {
+        GitService::GitHub {
+            ref user, ref repo, ..
+        } => {
+            assert_eq!(user, "rhysd", "{:?}", path);
+            assert_eq!(repo, "detect_git_service", "{:?}", path);
+        }
+        _ => panic!("unexpected service for {:?}: {:?}", path, service),
+    }
+}
+
+// Note: CARGO_TARGET_TMPDIR is put inside target directory of this repository
+fn nested_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(name)
+        .join("a")
+        .join("b")
+        .join("c");
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[test]
+fn nested_subdirectory() {
+    let dir = nested_dir("nested_subdirectory");
+    assert_this_repo(&dir);
+    for parent in dir.ancestors().take(3) {
+        assert_this_repo(parent);
+    }
+}
+
+#[test]
+fn file_in_nested_subdirectory() {
+    let file = nested_dir("file_in_nested_subdirectory").join("foo.txt");
+    fs::write(&file, "hello\n").unwrap();
+    assert_this_repo(&file);
+}
+
+#[test]
+fn nonexistent_path_in_nested_subdirectory() {
+    let dir = nested_dir("nonexistent_path_in_nested_subdirectory");
+    assert_this_repo(&dir.join("d").join("e").join("f.rs"));
+}
+
+#[test]
+fn path_outside_repository() {
+    let dir = std::env::temp_dir()
+        .join("detect_git_service-path_outside_repository")
+        .join("a")
+        .join("b.rs");
+    match detect(&dir).unwrap_err() {
+        Error::NotAGitRepository { .. } => {}
+        err => panic!("unexpected error for {:?}: {:?}", dir, err),
+    }
+}
+
+#[test]
+fn relative_paths() {
+    assert_this_repo(Path::new("src"));
+    assert_this_repo(Path::new("LICENSE"));
+    assert_this_repo(Path::new("src/lib.rs"));
+}
+
+#[test]
+fn linked_worktree() {
+    let root = std::env::temp_dir().join(format!(
+        "detect_git_service-linked_worktree-{}",
+        std::process::id()
+    ));
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    let main = root.join("main");
+    fs::create_dir_all(&main).unwrap();
+    git(&main, &["init", "-q"]);
+    git(&main, &["checkout", "-q", "-b", "main"]);
+    git(
+        &main,
+        &[
+            "remote",
+            "add",
+            "origin",
+            "https://github.com/rhysd/detect_git_service.git",
+        ],
+    );
+    git(&main, &["commit", "-q", "--allow-empty", "-m", "init"]);
+    let wt = root.join("wt");
+    git(
+        &main,
+        &[
+            "worktree",
+            "add",
+            "-q",
+            "-b",
+            "feature",
+            wt.to_str().unwrap(),
+        ],
+    );
+    fs::create_dir_all(wt.join("src")).unwrap();
+
+    let service = detect(&main).unwrap();
+    assert_eq!(service.branch().as_deref(), Some("main"));
+    for path in &[wt.clone(), wt.join("src"), wt.join("src").join("lib.rs")] {
+        let service = detect(path).unwrap();
+        assert_eq!(service.user(), "rhysd", "{:?}", path);
+        assert_eq!(service.repo(), "detect_git_service", "{:?}", path);
+        assert_eq!(service.branch().as_deref(), Some("feature"), "{:?}", path);
+    }
+}
+
+// Detection must not execute SSH even if `core.sshCommand` is configured
+#[cfg(unix)]
+#[test]
+fn ssh_command_is_never_executed() {
+    let root = std::env::temp_dir().join(format!(
+        "detect_git_service-ssh_command-{}",
+        std::process::id()
+    ));
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    let repo = root.join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    let marker = root.join("ssh-was-executed");
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["checkout", "-q", "-b", "main"]);
+    git(
+        &repo,
+        &[
+            "remote",
+            "add",
+            "origin",
+            "git@github.com:rhysd/detect_git_service.git",
+        ],
+    );
+    git(
+        &repo,
+        &[
+            "config",
+            "core.sshCommand",
+            &format!("touch '{}'; false", marker.display()),
+        ],
+    );
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "init"]);
+
+    let service = detect(&repo).unwrap();
+    assert_eq!(service.user(), "rhysd");
+    assert_eq!(service.repo(), "detect_git_service");
+    assert_eq!(service.branch().as_deref(), Some("main"));
+    assert_eq!(detect_all_remotes(&repo).unwrap().len(), 1);
+    assert!(!marker.exists(), "SSH was executed while detection");
+}